@@ -0,0 +1,40 @@
+//! Command line parsing shared by the tools. Flags and options are taken out of the arguments as they
+//! are read, so whatever is left over is positional (or unknown).
+
+/// Removes `name` from `args`, returning whether it was there
+pub fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|a| a == name) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes `name` and the value following it from `args`, returning the value
+pub fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|a| a == name) {
+        Some(i) if i + 1 < args.len() => {
+            let value = args.remove(i + 1);
+            args.remove(i);
+            Ok(Some(value))
+        }
+        Some(_) => Err(format!("{} needs a value", name)),
+        None => Ok(None),
+    }
+}
+
+/// Removes every `name` and the value following it from `args`, returning the values in order
+pub fn take_options(args: &mut Vec<String>, name: &str) -> Result<Vec<String>, String> {
+    let mut values = Vec::new();
+    while let Some(value) = take_option(args, name)? {
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// The first argument left in `args` that looks like a flag, for reporting options that weren't recognised
+pub fn unknown_flag(args: &[String]) -> Option<&String> {
+    args.iter().find(|a| a.starts_with("--"))
+}
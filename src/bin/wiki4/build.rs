@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+use hhmmss::Hhmmss;
+use wiki_4::args::{take_flag, take_option, unknown_flag};
+use wiki_4::builder::{parse_page, Builder, NamespaceFilter};
+use wiki_4::config::Config;
+use wiki_4::dump::{self, DumpReader};
+use wiki_4::namespace;
+use wiki_4::wikitext::{LinkOptions, WikiLink};
+
+/// How many streams workers may get ahead of the writer, to bound the memory used by parsed pages
+/// waiting to be written in order
+const STREAMS_AHEAD_PER_THREAD: usize = 16;
+
+/// Runs `work` on each of `0..count` on `threads` workers, passing the results to `write` on this
+/// thread in order. Workers wait rather than get more than `threads * STREAMS_AHEAD_PER_THREAD` ahead
+/// of the writer. If `write` fails the workers stop and its error is returned.
+fn in_order<T: Send, E>(
+    count: usize,
+    threads: usize,
+    work: impl Fn(usize) -> T + Sync,
+    mut write: impl FnMut(T) -> Result<(), E>,
+) -> Result<(), E> {
+    let ahead = threads * STREAMS_AHEAD_PER_THREAD;
+    let next = AtomicUsize::new(0);
+    // Results written so far, or `None` once the writer has stopped
+    let written = Mutex::new(Some(0));
+    let written_changed = Condvar::new();
+    let (sender, receiver) = mpsc::sync_channel::<(usize, T)>(threads * 4);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (next, written, written_changed, work) = (&next, &written, &written_changed, &work);
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= count {
+                    break;
+                }
+                let allowed = written_changed.wait_while(written.lock().unwrap(), |written| {
+                    written.is_some_and(|written| i >= written + ahead)
+                }).unwrap();
+                if allowed.is_none() {
+                    break;
+                }
+                drop(allowed);
+
+                if sender.send((i, work(i))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Results come in out of order - hold on to them until it is their turn
+        let mut pending = BTreeMap::new();
+        let mut next_to_write = 0;
+        for (i, result) in receiver {
+            pending.insert(i, result);
+            while let Some(result) = pending.remove(&next_to_write) {
+                let wrote = write(result);
+                next_to_write += 1;
+                *written.lock().unwrap() = wrote.is_ok().then_some(next_to_write);
+                written_changed.notify_all();
+                wrote?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Pages in the stream of the dump between `start` and `end`, skipping what can't be read
+fn parse_stream(dump_path: &str, start: u64, end: u64, filter: &NamespaceFilter, link_options: &LinkOptions) -> Vec<(String, Vec<WikiLink>, bool)> {
+    let mut pages = Vec::with_capacity(100);
+    match dump::open_stream(dump_path, start, end) {
+        Ok(stream) => {
+            for page in DumpReader::fragment(stream, filter.namespaces().clone()) {
+                match page {
+                    Ok(page) => match parse_page(page, filter, link_options) {
+                        Ok(page) => pages.extend(page),
+                        Err(e) => println!("{}", e),
+                    },
+                    Err(e) => {
+                        println!("Skipping rest of stream at offset {} due to error reading dump: {:?}", start, e);
+                        break;
+                    }
+                }
+            }
+        }
+        Err(e) => println!("Skipping stream at offset {} due to error: {:?}", start, e),
+    }
+    pages
+}
+
+/// Parses the streams of a multistream dump on `threads` workers, passing the pages to `cache` on
+/// this thread in dump order so ids are assigned the same way as a sequential run
+fn process_streams(
+    dump_path: &str,
+    offsets: &[u64],
+    filter: &NamespaceFilter,
+    link_options: &LinkOptions,
+    threads: usize,
+    mut cache: impl FnMut(String, Vec<WikiLink>, bool) -> Result<(), String>,
+) -> Result<(), String> {
+    let dump_length = fs::metadata(dump_path).map_err(|e| format!("Failed to read dump '{}': {}", dump_path, e))?.len();
+    let streams: Vec<(u64, u64)> = offsets.iter().copied()
+        .zip(offsets.iter().skip(1).copied().chain([dump_length]))
+        .collect();
+    println!("Parsing {} streams on {} threads", streams.len(), threads);
+
+    in_order(
+        streams.len(),
+        threads,
+        |i| parse_stream(dump_path, streams[i].0, streams[i].1, filter, link_options),
+        |pages| {
+            for (title, links, is_redirect) in pages {
+                cache(title, links, is_redirect)?;
+            }
+            Ok(())
+        },
+    )
+}
+
+pub fn run(mut args: Vec<String>, config: &Config) -> Result<(), String> {
+    // wiki4 build [--threads <n>] [--namespaces <comma separated keys or names, default 0>]
+    //             [--exclude-sections <comma separated headings>] [--template-links] [--ref-links] [--no-caption-links]
+    //             [--rewrite-redirects] [--link-details]
+    let threads = take_option(&mut args, "--threads")?;
+    let namespace_list = take_option(&mut args, "--namespaces")?;
+    let excluded_sections = take_option(&mut args, "--exclude-sections")?;
+    let rewrite_redirects = take_flag(&mut args, "--rewrite-redirects");
+    let link_details = take_flag(&mut args, "--link-details");
+    let mut link_options = LinkOptions {
+        template_links: take_flag(&mut args, "--template-links"),
+        ref_links: take_flag(&mut args, "--ref-links"),
+        caption_links: !take_flag(&mut args, "--no-caption-links"),
+        ..LinkOptions::default()
+    };
+    if let Some(flag) = unknown_flag(&args) {
+        return Err(format!("Unknown option '{}'", flag));
+    }
+    let dump_path = config.dump.clone();
+    let threads = match threads.map(|t| t.parse::<usize>()) {
+        Some(Ok(threads)) if threads > 0 => threads,
+        Some(_) => return Err("--threads needs a positive number".to_string()),
+        // One core is left for the writer
+        None => thread::available_parallelism().map(|n| n.get().saturating_sub(1).max(1)).unwrap_or(1),
+    };
+    let namespace_list = namespace_list.unwrap_or_else(|| namespace::MAIN.to_string());
+    if let Some(excluded_sections) = excluded_sections {
+        link_options.excluded_sections = excluded_sections.split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+    }
+
+    let start = Instant::now();
+    println!("Reading dump '{}'", dump_path);
+
+    let offsets = match &config.index {
+        Some(index_path) => {
+            println!("Reading multistream index '{}'", index_path);
+            let offsets = dump::read_index(index_path).map_err(|e| format!("Failed to read multistream index '{}': {}", index_path, e))?;
+            if offsets.is_empty() {
+                return Err(format!("Multistream index '{}' doesn't list any streams", index_path));
+            }
+            Some(offsets)
+        }
+        None => None,
+    };
+
+    let source = match &offsets {
+        Some(offsets) => dump::open_stream(&dump_path, 0, offsets[0]).map(|stream| Box::new(stream) as Box<dyn BufRead>),
+        None => dump::open_dump(&dump_path),
+    }.map_err(|e| format!("Failed to open dump '{}': {}", dump_path, e))?;
+    let reader = DumpReader::new(source).map_err(|e| format!("Failed to read dump '{}': {}", dump_path, e))?;
+
+    let filter = NamespaceFilter::new(reader.namespaces().clone(), &namespace_list)?;
+
+    let building_path = config.building_path();
+    let write_error = |e: rusqlite::Error| format!("Failed to write database '{}': {}", building_path, e);
+    let mut db = Builder::new(&building_path, config.batch_size, config.pages_per_write, config.links_per_write, link_details)
+        .map_err(write_error)?;
+    db.report_progress(|message| println!("{}", message));
+
+    let mut count: u32 = 0;
+    const TOTAL_ARTICLES: u32 = 23_100_000;
+
+    let mut cache = |title: String, links: Vec<WikiLink>, is_redirect: bool| {
+        db.cache(title, links, is_redirect).map_err(write_error)?;
+
+        count += 1;
+        if count.is_multiple_of(100_000) {
+            if count < TOTAL_ARTICLES {
+                println!("Completed {} articles in {} [{:?}/article]. ETA: {}", count, start.elapsed().hhmmss(), start.elapsed() / count, ((start.elapsed() / count) * (TOTAL_ARTICLES - count)).hhmmss())
+            }
+            else {
+                println!("Completed {} articles in {} [{:?}/article]", count, start.elapsed().hhmmss(), start.elapsed() / count);
+            }
+        }
+        Ok(())
+    };
+
+    match &offsets {
+        Some(offsets) => process_streams(&dump_path, offsets, &filter, &link_options, threads, &mut cache)?,
+        None => {
+            for page in reader {
+                let page = match page {
+                    Ok(page) => page,
+                    Err(e) => {
+                        println!("Breaking main loop due to error reading dump: {:?}", e);
+                        break;
+                    }
+                };
+
+                match parse_page(page, &filter, &link_options) {
+                    Ok(Some((title, links, is_redirect))) => cache(title, links, is_redirect)?,
+                    Ok(None) => {}
+                    Err(e) => println!("{}", e),
+                }
+            }
+        }
+    }
+
+    db.finish(rewrite_redirects).map_err(write_error)?;
+    fs::rename(&building_path, &config.database)
+        .map_err(|e| format!("Failed to move '{}' to '{}': {}", building_path, config.database, e))?;
+    // A snapshot of the previous database would be refused anyway, so don't leave it lying around
+    if Path::new(&config.snapshot).exists() {
+        fs::remove_file(&config.snapshot).map_err(|e| format!("Failed to remove '{}': {}", config.snapshot, e))?;
+        println!("Removed the previous database's graph snapshot '{}' - re-run wiki4 export to make a new one", config.snapshot);
+    }
+
+    println!("Completed {} articles in {} [{:?}/article]", count, start.elapsed().hhmmss(), start.elapsed() / count.max(1));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_written_in_order() {
+        // The first stream can't finish until the second has, so the second has to wait its turn
+        let (second_done, wait_for_second) = mpsc::channel();
+        let wait_for_second = Mutex::new(wait_for_second);
+        let finished = Mutex::new(Vec::new());
+        let mut written = Vec::new();
+        let result: Result<(), ()> = in_order(
+            50,
+            4,
+            |i| {
+                if i == 0 {
+                    wait_for_second.lock().unwrap().recv().unwrap();
+                }
+                finished.lock().unwrap().push(i);
+                if i == 1 {
+                    second_done.send(()).unwrap();
+                }
+                i
+            },
+            |i| {
+                written.push(i);
+                Ok(())
+            },
+        );
+        assert!(result.is_ok());
+        assert_eq!(written, (0..50).collect::<Vec<_>>());
+        let finished = finished.into_inner().unwrap();
+        assert!(finished.iter().position(|&i| i == 1) < finished.iter().position(|&i| i == 0));
+
+        // A failed write stops everything, even with workers waiting for the writer to catch up
+        let mut written = 0;
+        let result = in_order(10_000, 2, |i| i, |i| {
+            written += 1;
+            if i == 5 { Err(i) } else { Ok(()) }
+        });
+        assert_eq!(result, Err(5));
+        assert_eq!(written, 6);
+    }
+}
@@ -0,0 +1,314 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use hhmmss::Hhmmss;
+use num_format::{Locale, ToFormattedString};
+use rusqlite::Connection;
+use wiki_4::args::{take_option, unknown_flag};
+use wiki_4::csr::IndexedGraph;
+use wiki_4::config::Config;
+use crate::open_db;
+
+const DEFAULT_DAMPING: f64 = 0.85;
+const DEFAULT_ITERATIONS: usize = 50;
+const DEFAULT_TOP: usize = 20;
+/// Total change in rank between iterations below which PageRank has converged
+const TOLERANCE: f64 = 1e-10;
+
+/// PageRank of every page. Pages without links share their rank among every page.
+fn page_rank(graph: &IndexedGraph, damping: f64, iterations: usize) -> Vec<f64> {
+    let start = Instant::now();
+    let node_count = graph.node_count();
+    let mut rank = vec![1.0 / node_count as f64; node_count];
+    let mut next = vec![0.0; node_count];
+
+    for iteration in 1..=iterations {
+        next.fill(0.0);
+        let mut dangling_rank = 0.0;
+        for (page, &page_rank) in rank.iter().enumerate() {
+            let links = graph.links(page as u32);
+            if links.is_empty() {
+                dangling_rank += page_rank;
+                continue;
+            }
+
+            let share = page_rank / links.len() as f64;
+            for &link in links {
+                next[link as usize] += share;
+            }
+        }
+
+        let base = (1.0 - damping + damping * dangling_rank) / node_count as f64;
+        let mut change = 0.0;
+        for (page_rank, &linked) in rank.iter_mut().zip(&next) {
+            let new_rank = base + damping * linked;
+            change += (new_rank - *page_rank).abs();
+            *page_rank = new_rank;
+        }
+
+        println!("PageRank iteration {} changed ranks by {:.3e} [{}]", iteration, change, start.elapsed().hhmmss());
+        if change < TOLERANCE {
+            break;
+        }
+    }
+
+    rank
+}
+
+/// xorshift64 - good enough to pick sample pages without another dependency
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+/// Approximate betweenness centrality from Brandes' algorithm run from `samples` random pages, scaled
+/// up to estimate the value over every page
+fn sampled_betweenness(graph: &IndexedGraph, samples: usize, seed: u64) -> Vec<f64> {
+    let start = Instant::now();
+    let node_count = graph.node_count();
+    let mut centrality = vec![0.0; node_count];
+
+    // Reset after each sample for the pages it reached only
+    let mut distance = vec![u32::MAX; node_count];
+    let mut paths = vec![0.0f64; node_count];
+    let mut dependency = vec![0.0f64; node_count];
+    let mut order: Vec<u32> = Vec::new();
+    let mut random = seed.max(1);
+
+    for sample in 1..=samples {
+        let source = (next_random(&mut random) % node_count as u64) as u32;
+
+        distance[source as usize] = 0;
+        paths[source as usize] = 1.0;
+        order.push(source);
+        let mut next = 0;
+        while next < order.len() {
+            let page = order[next];
+            next += 1;
+            for &link in graph.links(page) {
+                if distance[link as usize] == u32::MAX {
+                    distance[link as usize] = distance[page as usize] + 1;
+                    order.push(link);
+                }
+                if distance[link as usize] == distance[page as usize] + 1 {
+                    paths[link as usize] += paths[page as usize];
+                }
+            }
+        }
+
+        // Furthest pages first so every page's dependency is complete before it is passed back
+        for &page in order.iter().rev() {
+            let mut page_dependency = 0.0;
+            for &link in graph.links(page) {
+                if distance[link as usize] == distance[page as usize] + 1 {
+                    page_dependency += paths[page as usize] / paths[link as usize] * (1.0 + dependency[link as usize]);
+                }
+            }
+            dependency[page as usize] = page_dependency;
+            if page != source {
+                centrality[page as usize] += page_dependency;
+            }
+        }
+
+        for &page in &order {
+            distance[page as usize] = u32::MAX;
+            paths[page as usize] = 0.0;
+            dependency[page as usize] = 0.0;
+        }
+        order.clear();
+
+        if sample.is_multiple_of(100) {
+            println!("Betweenness sampled from {} pages [{:?}/sample]", sample, start.elapsed() / sample as u32);
+        }
+    }
+
+    let scale = node_count as f64 / samples as f64;
+    for value in &mut centrality {
+        *value *= scale;
+    }
+    centrality
+}
+
+/// Replaces `column` of `pages` with `scores`
+fn write_scores(conn: &mut Connection, graph: &IndexedGraph, column: &str, scores: &[f64]) {
+    let start = Instant::now();
+    println!("Writing {} to pages", column);
+
+    // Left over from a previous run
+    let _ = conn.execute(&format!("ALTER TABLE pages DROP COLUMN {}", column), ());
+    conn.execute(&format!("ALTER TABLE pages ADD COLUMN {} REAL", column), ()).unwrap();
+
+    let tx = conn.transaction().unwrap();
+    let mut stmt = tx.prepare(&format!("UPDATE pages SET {} = ? WHERE id = ?", column)).unwrap();
+    for (index, score) in scores.iter().enumerate() {
+        stmt.execute((score, graph.id(index as u32))).unwrap();
+    }
+    drop(stmt);
+    tx.commit().unwrap();
+
+    println!("Finished writing {} in {}", column, start.elapsed().hhmmss());
+}
+
+pub fn run(mut args: Vec<String>, config: &Config) -> Result<(), String> {
+    // wiki4 centrality [--damping <0-1, default 0.85>] [--iterations <n, default 50>] [--betweenness <samples>]
+    //                  [--seed <n>] [--top <n>] [--output <tsv path>]
+    let damping = match take_option(&mut args, "--damping").map(|d| d.map(|d| d.parse::<f64>())) {
+        Ok(None) => DEFAULT_DAMPING,
+        Ok(Some(Ok(damping))) if (0.0..=1.0).contains(&damping) => damping,
+        _ => return Err("--damping needs a number between 0 and 1".to_string()),
+    };
+    let iterations = match take_option(&mut args, "--iterations").map(|i| i.map(|i| i.parse::<usize>())) {
+        Ok(None) => DEFAULT_ITERATIONS,
+        Ok(Some(Ok(iterations))) => iterations,
+        _ => return Err("--iterations needs a number".to_string()),
+    };
+    let samples = match take_option(&mut args, "--betweenness").map(|s| s.map(|s| s.parse::<usize>())) {
+        Ok(None) => 0,
+        Ok(Some(Ok(samples))) => samples,
+        _ => return Err("--betweenness needs a number of pages to sample".to_string()),
+    };
+    let seed = match take_option(&mut args, "--seed").map(|s| s.map(|s| s.parse::<u64>())) {
+        Ok(None) => SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64,
+        Ok(Some(Ok(seed))) => seed,
+        _ => return Err("--seed needs a number".to_string()),
+    };
+    let top = match take_option(&mut args, "--top").map(|t| t.map(|t| t.parse::<usize>())) {
+        Ok(None) => DEFAULT_TOP,
+        Ok(Some(Ok(top))) => top,
+        _ => return Err("--top needs a number".to_string()),
+    };
+    let output_path = take_option(&mut args, "--output")?;
+    if let Some(flag) = unknown_flag(&args) {
+        return Err(format!("Unknown option '{}'", flag));
+    }
+
+    let start = Instant::now();
+
+    let mut conn = open_db(config)?;
+    let graph = IndexedGraph::load(&conn, &config.snapshot)?;
+    println!("Loaded {} pages in {}", graph.node_count().to_formatted_string(&Locale::en), start.elapsed().hhmmss());
+    if graph.node_count() == 0 {
+        return Err("There are no pages to rank".to_string());
+    }
+
+    let rank = page_rank(&graph, damping, iterations);
+    write_scores(&mut conn, &graph, "page_rank", &rank);
+
+    let betweenness = if samples > 0 {
+        let betweenness = sampled_betweenness(&graph, samples, seed);
+        write_scores(&mut conn, &graph, "betweenness", &betweenness);
+        Some(betweenness)
+    }
+    else {
+        None
+    };
+
+    // Redirects only pass their rank on, so they aren't worth ranking
+    let mut ranked: Vec<u32> = (0..graph.node_count() as u32).filter(|&i| !graph.is_redirect(i)).collect();
+    ranked.sort_unstable_by(|&a, &b| rank[b as usize].total_cmp(&rank[a as usize]));
+
+    println!("Highest PageRank:");
+    for (position, &index) in ranked.iter().take(top).enumerate() {
+        match &betweenness {
+            Some(betweenness) => println!(
+                "{:>6}. {} ({:.3e}, betweenness {:.3e})",
+                position + 1,
+                graph.title(index),
+                rank[index as usize],
+                betweenness[index as usize]
+            ),
+            None => println!("{:>6}. {} ({:.3e})", position + 1, graph.title(index), rank[index as usize]),
+        }
+    }
+
+    if let Some(output_path) = output_path {
+        let mut writer = BufWriter::new(File::create(&output_path).unwrap());
+        writeln!(writer, "rank\ttitle\tpage_rank\tbetweenness").unwrap();
+        for (position, &index) in ranked.iter().enumerate() {
+            let betweenness = betweenness.as_ref().map(|b| b[index as usize].to_string()).unwrap_or_default();
+            writeln!(writer, "{}\t{}\t{}\t{}", position + 1, graph.title(index), rank[index as usize], betweenness).unwrap();
+        }
+        writer.flush().unwrap();
+        println!("Wrote the ranking to '{}'", output_path);
+    }
+
+    println!("Completed in {}", start.elapsed().hhmmss());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_graph::{graph, index};
+
+    const LEAVES: [&str; 4] = ["North", "East", "South", "West"];
+
+    /// A hub linking to and from each of four leaves
+    fn star() -> IndexedGraph {
+        let mut pages: Vec<(&str, &[&str])> = LEAVES.iter().map(|&leaf| (leaf, &["Hub"][..])).collect();
+        pages.push(("Hub", &LEAVES));
+        graph(&pages)
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() < tolerance, "expected {} but got {}", expected, actual);
+    }
+
+    #[test]
+    fn star_page_rank() {
+        let graph = star();
+        let rank = page_rank(&graph, 0.85, 100);
+
+        // hub = base + 0.85 * 4 * leaf and leaf = base + 0.85 * hub / 4, solved for hub
+        let base = 0.15 / 5.0;
+        let hub = (base + 0.85 * 4.0 * base) / (1.0 - 0.85 * 0.85);
+        assert_close(rank[index(&graph, "Hub") as usize], hub, 1e-6);
+        for leaf in LEAVES {
+            assert_close(rank[index(&graph, leaf) as usize], base + 0.85 * hub / 4.0, 1e-6);
+        }
+        assert_close(rank.iter().sum(), 1.0, 1e-9);
+    }
+
+    #[test]
+    fn chain_page_rank() {
+        // C links nowhere, so its rank is shared among every page
+        let graph = graph(&[("A", &["B"]), ("B", &["C"]), ("C", &[])]);
+        let rank = page_rank(&graph, 0.85, 100);
+
+        // Every page gets the same base, and each page passes 0.85 of its rank along
+        let base = 1.0 / (1.0 + 1.85 + (1.0 + 0.85 * 1.85));
+        assert_close(rank[index(&graph, "A") as usize], base, 1e-6);
+        assert_close(rank[index(&graph, "B") as usize], base * 1.85, 1e-6);
+        assert_close(rank[index(&graph, "C") as usize], base * (1.0 + 0.85 * 1.85), 1e-6);
+        assert_close(rank.iter().sum(), 1.0, 1e-9);
+    }
+
+    #[test]
+    fn star_betweenness() {
+        let graph = star();
+        let betweenness = sampled_betweenness(&graph, 10_000, 7);
+
+        // Every path between two leaves goes through the hub
+        assert_close(betweenness[index(&graph, "Hub") as usize], 12.0, 0.5);
+        for leaf in LEAVES {
+            assert_eq!(betweenness[index(&graph, leaf) as usize], 0.0);
+        }
+    }
+
+    #[test]
+    fn chain_betweenness() {
+        let graph = graph(&[("A", &["B"]), ("B", &["C"]), ("C", &["D"]), ("D", &[])]);
+        let betweenness = sampled_betweenness(&graph, 10_000, 7);
+
+        // B is on A-C and A-D, C on A-D and B-D, and the ends are on nothing
+        assert_close(betweenness[index(&graph, "B") as usize], 2.0, 0.2);
+        assert_close(betweenness[index(&graph, "C") as usize], 2.0, 0.2);
+        assert_eq!(betweenness[index(&graph, "A") as usize], 0.0);
+        assert_eq!(betweenness[index(&graph, "D") as usize], 0.0);
+
+        // The same seed samples the same pages
+        assert_eq!(sampled_betweenness(&graph, 100, 7), sampled_betweenness(&graph, 100, 7));
+    }
+}
@@ -0,0 +1,200 @@
+use std::time::Instant;
+use hhmmss::Hhmmss;
+use num_format::{Locale, ToFormattedString};
+use wiki_4::args::unknown_flag;
+use wiki_4::csr::IndexedGraph;
+use wiki_4::config::Config;
+use crate::open_db;
+
+const UNVISITED: u32 = u32::MAX;
+
+/// Tarjan's algorithm with an explicit stack, returning each page's component and each component's
+/// size. Components are numbered in the order Tarjan finds them, which is a reverse topological order:
+/// a link between two components always goes from a higher number to a lower one.
+fn strongly_connected_components(graph: &IndexedGraph) -> (Vec<u32>, Vec<u32>) {
+    let start = Instant::now();
+    let node_count = graph.node_count();
+
+    let mut index = vec![UNVISITED; node_count];
+    let mut lowlink = vec![0u32; node_count];
+    let mut on_stack = vec![false; node_count];
+    let mut stack: Vec<u32> = Vec::new();
+    let mut component = vec![UNVISITED; node_count];
+    let mut sizes: Vec<u32> = Vec::new();
+    // Pages being visited and how many of their links have been followed so far
+    let mut calls: Vec<(u32, usize)> = Vec::new();
+    let mut next_index = 0;
+
+    for root in 0..node_count as u32 {
+        if index[root as usize] != UNVISITED {
+            continue;
+        }
+
+        index[root as usize] = next_index;
+        lowlink[root as usize] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root as usize] = true;
+        calls.push((root, 0));
+
+        while let Some((page, followed)) = calls.last_mut() {
+            let page = *page;
+            let links = graph.links(page);
+
+            if *followed < links.len() {
+                let link = links[*followed];
+                *followed += 1;
+
+                if index[link as usize] == UNVISITED {
+                    index[link as usize] = next_index;
+                    lowlink[link as usize] = next_index;
+                    next_index += 1;
+                    stack.push(link);
+                    on_stack[link as usize] = true;
+                    calls.push((link, 0));
+                }
+                else if on_stack[link as usize] {
+                    lowlink[page as usize] = lowlink[page as usize].min(index[link as usize]);
+                }
+                continue;
+            }
+
+            calls.pop();
+            if let Some(&(parent, _)) = calls.last() {
+                lowlink[parent as usize] = lowlink[parent as usize].min(lowlink[page as usize]);
+            }
+
+            if lowlink[page as usize] == index[page as usize] {
+                let id = sizes.len() as u32;
+                let mut size = 0;
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack[member as usize] = false;
+                    component[member as usize] = id;
+                    size += 1;
+                    if member == page {
+                        break;
+                    }
+                }
+                sizes.push(size);
+            }
+        }
+
+        if root.is_multiple_of(1_000_000) && root > 0 {
+            println!(
+                "Visited {} pages, finding {} components [{}]",
+                next_index.to_formatted_string(&Locale::en),
+                sizes.len().to_formatted_string(&Locale::en),
+                start.elapsed().hhmmss()
+            );
+        }
+    }
+
+    (component, sizes)
+}
+
+pub fn run(args: Vec<String>, config: &Config) -> Result<(), String> {
+    // wiki4 components
+    if let Some(flag) = unknown_flag(&args) {
+        return Err(format!("Unknown option '{}'", flag));
+    }
+
+    let start = Instant::now();
+
+    let mut conn = open_db(config)?;
+    let graph = IndexedGraph::load(&conn, &config.snapshot)?;
+    println!("Loaded {} pages in {}", graph.node_count().to_formatted_string(&Locale::en), start.elapsed().hhmmss());
+
+    println!("Finding strongly connected components");
+    let (component, sizes) = strongly_connected_components(&graph);
+    println!("Found {} components in {}", sizes.len().to_formatted_string(&Locale::en), start.elapsed().hhmmss());
+
+    println!("Writing components to pages");
+    for column in ["component", "component_size"] {
+        // Left over from a previous run
+        let _ = conn.execute(&format!("ALTER TABLE pages DROP COLUMN {}", column), ());
+        conn.execute(&format!("ALTER TABLE pages ADD COLUMN {} INTEGER", column), ()).unwrap();
+    }
+    let tx = conn.transaction().unwrap();
+    let mut stmt = tx.prepare("UPDATE pages SET component = ?, component_size = ? WHERE id = ?").unwrap();
+    for (index, &id) in component.iter().enumerate() {
+        stmt.execute((id, sizes[id as usize], graph.id(index as u32))).unwrap();
+    }
+    drop(stmt);
+    tx.commit().unwrap();
+
+    let node_count = graph.node_count().max(1);
+    let mut by_size: Vec<u32> = (0..sizes.len() as u32).collect();
+    by_size.sort_unstable_by_key(|&id| std::cmp::Reverse(sizes[id as usize]));
+    if let Some(&giant) = by_size.first() {
+        let giant_size = sizes[giant as usize];
+        let example = component.iter().position(|&id| id == giant).unwrap() as u32;
+        println!(
+            "Giant component {} has {} pages ({:.2}% of all pages), including '{}'",
+            giant,
+            giant_size.to_formatted_string(&Locale::en),
+            100.0 * giant_size as f64 / node_count as f64,
+            graph.title(example)
+        );
+    }
+    if let Some(&second) = by_size.get(1) {
+        println!("The next largest component has {} pages", sizes[second as usize].to_formatted_string(&Locale::en));
+    }
+    let singletons = sizes.iter().filter(|&&size| size == 1).count();
+    println!(
+        "{} pages are in a component of their own - they can't be reached from any page they link to",
+        singletons.to_formatted_string(&Locale::en)
+    );
+
+    println!("Completed in {}", start.elapsed().hhmmss());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_graph::{graph, index};
+
+    #[test]
+    fn membership_and_order() {
+        // {A, B} and {C, D} are cycles, E and F are on their own, and F leads into the rest
+        let graph = graph(&[
+            ("A", &["B"]),
+            ("B", &["A", "C"]),
+            ("C", &["D"]),
+            ("D", &["C", "E"]),
+            ("E", &[]),
+            ("F", &["A"]),
+        ]);
+        let (component, sizes) = strongly_connected_components(&graph);
+        let of = |title| component[index(&graph, title) as usize];
+
+        assert_eq!(sizes.len(), 4);
+        assert_eq!(of("A"), of("B"));
+        assert_eq!(of("C"), of("D"));
+        assert_eq!(sizes[of("A") as usize], 2);
+        assert_eq!(sizes[of("C") as usize], 2);
+        assert_eq!(sizes[of("E") as usize], 1);
+        assert_eq!(sizes[of("F") as usize], 1);
+
+        // Every link between components goes to a lower number
+        assert!(of("F") > of("A") && of("A") > of("C") && of("C") > of("E"));
+        for page in 0..graph.node_count() as u32 {
+            for &link in graph.links(page) {
+                assert!(component[link as usize] <= component[page as usize]);
+            }
+        }
+    }
+
+    #[test]
+    fn long_cycle() {
+        // Deeper than a recursive search could go on a small stack
+        let titles: Vec<String> = (0..5000).map(|i| format!("Page {}", i)).collect();
+        let links: Vec<[&str; 1]> = (0..titles.len()).map(|i| [titles[(i + 1) % titles.len()].as_str()]).collect();
+        let pages: Vec<(&str, &[&str])> = titles.iter().zip(&links).map(|(title, links)| (title.as_str(), &links[..])).collect();
+        let (component, sizes) = strongly_connected_components(&graph(&pages));
+
+        assert_eq!(sizes, [5000]);
+        assert!(component.iter().all(|&id| id == 0));
+    }
+}
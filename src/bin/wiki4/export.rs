@@ -0,0 +1,34 @@
+use std::time::Instant;
+use hhmmss::Hhmmss;
+use num_format::{Locale, ToFormattedString};
+use wiki_4::args::unknown_flag;
+use wiki_4::config::Config;
+use wiki_4::csr::LinkTable;
+use crate::open_db;
+
+pub fn run(args: Vec<String>, config: &Config) -> Result<(), String> {
+    // wiki4 export
+    if let Some(flag) = unknown_flag(&args) {
+        return Err(format!("Unknown option '{}'", flag));
+    }
+
+    let start = Instant::now();
+
+    let conn = open_db(config)?;
+
+    println!("Reading pages and links");
+    let table = LinkTable::read(&conn).map_err(|e| format!("Failed to read database: {}", e))?;
+    println!(
+        "Read {} pages and {} links ({} dangling links skipped) in {}",
+        table.node_count().to_formatted_string(&Locale::en),
+        table.out_edges.len().to_formatted_string(&Locale::en),
+        table.dangling.to_formatted_string(&Locale::en),
+        start.elapsed().hhmmss()
+    );
+
+    println!("Writing snapshot '{}'", config.snapshot);
+    table.write_snapshot(&config.snapshot).map_err(|e| format!("Failed to write snapshot '{}': {}", config.snapshot, e))?;
+
+    println!("Completed in {}", start.elapsed().hhmmss());
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use std::path::Path;
+use rusqlite::Connection;
+use wiki_4::args::{take_flag, take_option, unknown_flag};
+use wiki_4::config::{json_string, Config, Format};
+use wiki_4::dump::{self, DumpReader};
+use wiki_4::page_id::title_key;
+use crate::{find_page, has_column, open_db};
+
+/// Links and backlinks listed unless `--limit` says otherwise
+const DEFAULT_LIMIT: usize = 20;
+
+/// Scores written to `pages` by `stats`, `components` and `centrality`, shown when they have been run
+const SCORES: [&str; 6] = ["in_degree", "out_degree", "component", "component_size", "page_rank", "betweenness"];
+
+/// Titles of the first `limit` pages at the other end of the links `from` column `end` of `id`,
+/// skipping links to pages that don't exist
+fn linked_titles(db: &Connection, end: &str, from: &str, id: i64, limit: usize) -> Vec<String> {
+    let mut stmt = db.prepare(&format!(
+        "SELECT pages.title FROM links JOIN pages ON pages.id = links.{} WHERE links.{} = ? ORDER BY pages.title LIMIT ?",
+        end, from
+    )).unwrap();
+    stmt.query_map((id, limit as i64), |row| row.get(0)).unwrap().map(|title| title.unwrap()).collect()
+}
+
+fn count_links(db: &Connection, column: &str, id: i64) -> i64 {
+    db.query_row(&format!("SELECT COUNT(*) FROM links WHERE {} = ?", column), [id], |row| row.get(0)).unwrap()
+}
+
+fn json_titles(titles: &[String]) -> String {
+    let titles: Vec<String> = titles.iter().map(|title| json_string(title)).collect();
+    format!("[{}]", titles.join(","))
+}
+
+pub fn run(mut args: Vec<String>, config: &Config) -> Result<(), String> {
+    // wiki4 inspect [--limit <n, default 20>] <title>
+    // wiki4 inspect --wikitext <title>
+    let wikitext = take_flag(&mut args, "--wikitext");
+    let limit = match take_option(&mut args, "--limit").map(|limit| limit.map(|l| l.parse::<usize>())) {
+        Ok(None) => DEFAULT_LIMIT,
+        Ok(Some(Ok(limit))) => limit,
+        _ => return Err("--limit needs a number".to_string()),
+    };
+    if let Some(flag) = unknown_flag(&args) {
+        return Err(format!("Unknown option '{}'", flag));
+    }
+    let [title] = match <[String; 1]>::try_from(args) {
+        Ok(title) => title,
+        Err(_) => return Err("Usage: wiki4 inspect [--limit <n>] <title> or wiki4 inspect --wikitext <title>".to_string()),
+    };
+
+    if wikitext {
+        print_wikitext(&title, config)
+    }
+    else {
+        print_page(&title, limit, config)
+    }
+}
+
+/// Prints what the database has on the page titled `title`: its links, backlinks and any scores
+fn print_page(title: &str, limit: usize, config: &Config) -> Result<(), String> {
+    let format = config.format;
+    let db = open_db(config)?;
+    let page = find_page(&db, title, format)?;
+
+    let links_out = count_links(&db, "source_id", page.id);
+    let links_in = count_links(&db, "destination_id", page.id);
+    let links = linked_titles(&db, "destination_id", "source_id", page.id, limit);
+    let backlinks = linked_titles(&db, "source_id", "destination_id", page.id, limit);
+
+    // Only the scores whose binary has been run, as text so integers and floats print as they are
+    let scores: Vec<(&str, Option<String>)> = SCORES.into_iter()
+        .filter(|column| has_column(&db, "pages", column))
+        .map(|column| {
+            let score: rusqlite::types::Value = db.query_row(
+                &format!("SELECT {} FROM pages WHERE id = ?", column),
+                [page.id],
+                |row| row.get(0)
+            ).unwrap();
+            let score = match score {
+                rusqlite::types::Value::Integer(n) => Some(n.to_string()),
+                rusqlite::types::Value::Real(x) => Some(x.to_string()),
+                _ => None,
+            };
+            (column, score)
+        })
+        .collect();
+
+    match format {
+        Format::Text => {
+            println!("{}", page.title);
+            println!("    Id: {}", page.id);
+            println!("    Links: {} out, {} in", links_out, links_in);
+            for (column, score) in &scores {
+                println!("    {}: {}", column, score.as_deref().unwrap_or("none"));
+            }
+            println!("Links to ({} shown):", links.len());
+            for title in &links {
+                println!("    {}", title);
+            }
+            println!("Linked from ({} shown):", backlinks.len());
+            for title in &backlinks {
+                println!("    {}", title);
+            }
+        }
+        Format::Json => {
+            let scores: Vec<String> = scores.iter()
+                .map(|(column, score)| format!("\"{}\":{}", column, score.as_deref().unwrap_or("null")))
+                .collect();
+            println!(
+                "{{\"title\":{},\"id\":{},\"redirect_from\":{},\"links_out\":{},\"links_in\":{},\"links\":{},\"backlinks\":{},\"scores\":{{{}}}}}",
+                json_string(&page.title),
+                page.id,
+                page.redirect.as_deref().map_or("null".to_string(), json_string),
+                links_out,
+                links_in,
+                json_titles(&links),
+                json_titles(&backlinks),
+                scores.join(",")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the dump until the page titled `title` and prints its wikitext
+fn print_wikitext(title: &str, config: &Config) -> Result<(), String> {
+    let format = config.format;
+    if !Path::new(&config.dump).exists() {
+        return Err(format!("There is no dump at '{}'", config.dump));
+    }
+
+    format.note(format!("Searching '{}' for '{}'", config.dump, title));
+    let key = title_key(title);
+    let source = dump::open_dump(&config.dump).map_err(|e| format!("Failed to open dump '{}': {}", config.dump, e))?;
+    let reader = DumpReader::new(source).map_err(|e| format!("Failed to read dump '{}': {}", config.dump, e))?;
+    for page in reader {
+        let page = page.map_err(|e| format!("Failed to read dump: {:?}", e))?;
+        if title_key(&page.title) != key {
+            continue;
+        }
+
+        match format {
+            Format::Text => {
+                println!("{} (id {}, namespace {}, edited {})", page.title, page.id, page.namespace, page.timestamp);
+                if let Some(redirect) = &page.redirect {
+                    println!("Redirects to '{}'", redirect);
+                }
+                println!("{}", page.text);
+            }
+            Format::Json => println!(
+                "{{\"title\":{},\"id\":{},\"namespace\":{},\"timestamp\":{},\"redirect\":{},\"text\":{}}}",
+                json_string(&page.title),
+                page.id,
+                page.namespace,
+                json_string(&page.timestamp),
+                page.redirect.as_deref().map_or("null".to_string(), json_string),
+                json_string(&page.text)
+            ),
+        }
+        return Ok(());
+    }
+
+    Err(format!("There is no page titled '{}' in the dump", title))
+}
@@ -0,0 +1,159 @@
+use std::env;
+use std::path::Path;
+use std::process;
+use rusqlite::Connection;
+use wiki_4::config::{Config, Format};
+use wiki_4::csr::CsrGraph;
+use wiki_4::graph::{GraphError, SqliteGraph, WikiGraph};
+use wiki_4::lookup;
+use wiki_4::page_id;
+
+mod build;
+mod centrality;
+mod components;
+mod export;
+mod inspect;
+mod path;
+mod red_links;
+mod sample;
+mod stats;
+#[cfg(test)]
+mod test_graph;
+
+const USAGE: &str = "Usage: wiki4 [settings] <command> [options]
+
+Commands:
+    build      Build the database from a Wikipedia dump
+    export     Write the graph snapshot searches read links from
+    path       Find the shortest path between two pages
+    sample     Search between random pages and keep a rolling average of the path length
+    stats      Count each page's links and summarise the database
+    inspect    Show a page's links, or its wikitext in the dump with --wikitext
+    components Find the groups of pages that can all reach each other, so impossible searches are rejected
+    centrality Score pages by PageRank and, with --betweenness <samples>, betweenness centrality
+    red-links  Count the links to pages that don't exist, and delete them with --prune
+
+Settings (also WIKI4_<NAME> environment variables or `name = value` lines in wiki4.conf):
+    --config <path>            Config file to read instead of wiki4.conf
+    --dump <path>              .xml or .xml.bz2 dump to build from
+    --index <path>             Index of a multistream dump (found next to it by default)
+    --database <path>          Database to build and search (default completed-table.db)
+    --snapshot <path>          Graph snapshot (default completed-graph.csr)
+    --not-found <path>         Where sample records searches with no path (default not_found.txt)
+    --batch-size <n>           Rows per insert statement while building (default 1000)
+    --pages-per-write <n>      Pages cached before writing them while building (default 1000000)
+    --links-per-write <n>      Links cached before writing them while building (default 75000000)
+    --format <text|json>       How path, sample, stats and inspect print their results (default text)";
+
+/// Near misses listed when a title isn't found
+const SUGGESTIONS: usize = 10;
+
+/// Opens the database for reading, checking it was built with the current page id scheme
+fn open_db(config: &Config) -> Result<Connection, String> {
+    if !Path::new(&config.database).exists() {
+        return Err(format!("There is no database at '{}' - run wiki4 build first", config.database));
+    }
+
+    let db = Connection::open(&config.database).map_err(|e| format!("Failed to open database '{}': {}", config.database, e))?;
+    db.execute_batch(
+        "PRAGMA synchronous = 0;
+              PRAGMA cache_size = 1000000;
+              PRAGMA locking_mode = EXCLUSIVE;
+              PRAGMA temp_store = MEMORY;
+              PRAGMA journal_mode = OFF;"
+        ,
+    ).map_err(GraphError::from)?;
+
+    page_id::check_scheme(&db).map_err(|e| e.to_string())?;
+    Ok(db)
+}
+
+fn has_column(db: &Connection, table: &str, column: &str) -> bool {
+    db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info(?) WHERE name = ?)",
+        [table, column],
+        |row| row.get(0)
+    ).unwrap()
+}
+
+/// Where links are read from - the graph snapshot if there is one that matches the database, otherwise
+/// the database directly
+fn load_graph<'a>(db: &'a Connection, config: &Config) -> Result<Box<dyn WikiGraph + 'a>, String> {
+    if Path::new(&config.snapshot).exists() {
+        config.format.note(format!("Loading graph snapshot '{}'", config.snapshot));
+        match CsrGraph::open(&config.snapshot, db) {
+            Ok(graph) => return Ok(Box::new(graph)),
+            Err(e) => config.format.note(format!("Not using graph snapshot: {} (reading links from the database instead)", e)),
+        }
+    }
+
+    Ok(Box::new(SqliteGraph::new(db)?))
+}
+
+/// The page `title` refers to (after any redirect), or an error listing titles that might have been
+/// meant if there is no such page
+fn find_page(db: &Connection, title: &str, format: Format) -> Result<lookup::FoundPage, String> {
+    if let Some(page) = lookup::find_page(db, title).map_err(GraphError::from)? {
+        if let Some(redirect) = &page.redirect {
+            format.note(format!("'{}' redirects to '{}'", redirect, page.title));
+        }
+        return Ok(page);
+    }
+
+    let mut error = format!("There is no page titled '{}'", title);
+    if lookup::ensure_title_index(db).map_err(GraphError::from)? {
+        format.note("Created title index (this only needs to happen once)");
+    }
+    let suggestions = lookup::suggest(db, title, SUGGESTIONS).map_err(GraphError::from)?;
+    if !suggestions.is_empty() {
+        error += "\nDid you mean:";
+        for suggestion in suggestions {
+            match suggestion.redirects_to {
+                Some(target) => error += &format!("\n    {} (redirects to '{}')", suggestion.title, target),
+                None => error += &format!("\n    {}", suggestion.title),
+            }
+        }
+    }
+    Err(error)
+}
+
+fn main() {
+    // wiki4 [settings] <build | export | path | sample | stats | inspect | components | centrality | red-links> [options]
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let config = match Config::load(&mut args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if args.is_empty() {
+        println!("{}", USAGE);
+        return;
+    }
+    let command = args.remove(0);
+
+    let result = match command.as_str() {
+        "build" => build::run(args, &config),
+        "export" => export::run(args, &config),
+        "path" => path::run(args, &config),
+        "sample" => sample::run(args, &config),
+        "stats" => stats::run(args, &config),
+        "inspect" => inspect::run(args, &config),
+        "components" => components::run(args, &config),
+        "centrality" => centrality::run(args, &config),
+        "red-links" => red_links::run(args, &config),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("Unknown command '{}'\n{}", command, USAGE)),
+    };
+
+    // Errors go to stderr with a failing exit status whatever the format, so scripts can tell
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,466 @@
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use hhmmss::Hhmmss;
+use num_format::{Locale, ToFormattedString};
+use rusqlite::{Connection, OptionalExtension};
+use wiki_4::args::{take_flag, take_option, take_options, unknown_flag};
+use wiki_4::config::{json_string, Config, Format};
+use wiki_4::display::{fmt_path, json_path};
+use wiki_4::graph::{GraphError, WikiGraph};
+use wiki_4::page_id;
+use wiki_4::search::{self, Budget, Exclusions, GaveUp, Limit, Outcome};
+use crate::{find_page, has_column, load_graph, open_db};
+
+/// Paths printed by `--all-paths` unless `--limit` says otherwise
+const DEFAULT_PATH_LIMIT: usize = 100;
+
+/// `n` links, in words
+fn fmt_links(n: usize) -> String {
+    format!("{} {}", n, if n == 1 { "link" } else { "links" })
+}
+
+/// Why a search gave up, in words
+fn gave_up_reason(gave_up: &GaveUp, max_length: Option<usize>) -> String {
+    match gave_up.limit {
+        Limit::Length => format!("there is no path of {} or fewer", fmt_links(max_length.unwrap_or_default())),
+        Limit::Pages => "the most pages to search was reached".to_string(),
+        Limit::Time => "ran out of time".to_string(),
+        Limit::Memory => "the memory ceiling was reached".to_string(),
+        Limit::Interrupted => "interrupted".to_string(),
+    }
+}
+
+/// Explains why a search gave up and how far it had got
+fn print_gave_up(gave_up: &GaveUp, max_length: Option<usize>) {
+    println!("Gave up: {}", gave_up_reason(gave_up, max_length));
+    println!(
+        "Depth reached: {} | Seen: {} | Open set size: {}",
+        fmt_links(gave_up.depth),
+        gave_up.seen.to_formatted_string(&Locale::en),
+        gave_up.frontier.to_formatted_string(&Locale::en),
+    );
+}
+
+/// Strongly connected component the page `id` is in and the component's size, if wiki4 components has
+/// been run
+fn component(db: &Connection, id: i64) -> rusqlite::Result<Option<(i64, i64)>> {
+    db.query_row("SELECT component, component_size FROM pages WHERE id = ?", [id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()
+}
+
+/// Why there can't be a path from `start_id` to `end_id`, if that can be told without searching
+fn impossible_path(db: &Connection, graph: &mut dyn WikiGraph, start_id: i64, end_id: i64) -> Result<Option<String>, GraphError> {
+    if start_id == end_id {
+        return Ok(None);
+    }
+
+    if graph.links(start_id)?.is_empty() {
+        return Ok(Some(format!("'{}' doesn't link to any pages", graph.title(start_id)?)));
+    }
+    if graph.backlinks(end_id)?.is_empty() {
+        return Ok(Some(format!("no pages link to '{}'", graph.title(end_id)?)));
+    }
+
+    // Components are numbered so links between them always lead to a lower number - nothing in a higher
+    // numbered component than the start can be reached from it
+    if has_column(db, "pages", "component") {
+        if let (Some((start_component, start_size)), Some((end_component, end_size))) = (component(db, start_id)?, component(db, end_id)?) {
+            if end_component > start_component {
+                return Ok(Some(format!(
+                    "'{}' is in a group of {} pages that no chain of links from '{}' (in a group of {}) leads into",
+                    graph.title(end_id)?,
+                    end_size.to_formatted_string(&Locale::en),
+                    graph.title(start_id)?,
+                    start_size.to_formatted_string(&Locale::en)
+                )));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Pages the search has to avoid: those titled in `avoid`, or matching it for patterns containing `*`
+/// or `?`
+fn build_exclusions(db: &Connection, avoid: &[String], format: Format) -> Result<Exclusions, String> {
+    let mut exclusions = Exclusions::default();
+
+    for title in avoid {
+        if title.contains(['*', '?']) {
+            let mut stmt = db.prepare("SELECT id FROM pages WHERE title GLOB ?").map_err(GraphError::from)?;
+            let ids = stmt.query_map([title], |row| row.get(0))
+                .and_then(|ids| ids.collect::<rusqlite::Result<Vec<i64>>>())
+                .map_err(GraphError::from)?;
+            if ids.is_empty() {
+                return Err(format!("No titles match '{}'", title));
+            }
+            format.note(format!(
+                "Avoiding {} {} matching '{}'",
+                ids.len().to_formatted_string(&Locale::en),
+                if ids.len() == 1 { "page" } else { "pages" },
+                title
+            ));
+            exclusions.pages.extend(ids);
+            continue;
+        }
+
+        let page = find_page(db, title, format).map_err(|e| format!("Can't avoid '{}': {}", title, e))?;
+        // Links to the redirect itself are only avoided if the redirect is too
+        exclusions.pages.insert(page_id::resolve_title(db, title).map_err(GraphError::from)?);
+        exclusions.pages.insert(page.id);
+    }
+
+    Ok(exclusions)
+}
+
+/// Adds the pages with more than `max_degree` links in or out to `exclusions`, apart from `stops` - the
+/// hubs a path has to go around rather than start or end at
+fn exclude_hubs(db: &Connection, exclusions: &mut Exclusions, max_degree: i64, stops: &[i64], format: Format) -> Result<(), String> {
+    if !has_column(db, "pages", "in_degree") {
+        return Err("--max-degree needs the link counts from wiki4 stats - run it first".to_string());
+    }
+
+    let mut stmt = db.prepare("SELECT id FROM pages WHERE in_degree > ?1 OR out_degree > ?1").map_err(GraphError::from)?;
+    let mut hubs = stmt.query_map([max_degree], |row| row.get(0))
+        .and_then(|ids| ids.collect::<rusqlite::Result<Vec<i64>>>())
+        .map_err(GraphError::from)?;
+    hubs.retain(|id| !stops.contains(id));
+    format.note(format!(
+        "Avoiding {} {} with more than {} links in or out",
+        hubs.len().to_formatted_string(&Locale::en),
+        if hubs.len() == 1 { "page" } else { "pages" },
+        max_degree.to_formatted_string(&Locale::en)
+    ));
+    exclusions.pages.extend(hubs);
+
+    Ok(())
+}
+
+/// Prints the summary of a search `format` doesn't print as part of the result
+fn print_summary(start_time: Instant, budget: &Budget, seen: Option<(usize, usize)>) {
+    println!("Completed in {}", start_time.elapsed().hhmmssxxx());
+    match seen {
+        Some((seen, frontier)) => println!(
+            "Pages searched: {} [{:?}/page] | Seen: {} | Open set size: {}",
+            budget.pages_searched().to_formatted_string(&Locale::en),
+            start_time.elapsed() / budget.pages_searched().max(1),
+            seen.to_formatted_string(&Locale::en),
+            frontier.to_formatted_string(&Locale::en),
+        ),
+        None => println!(
+            "Pages searched: {} [{:?}/page]",
+            budget.pages_searched().to_formatted_string(&Locale::en),
+            start_time.elapsed() / budget.pages_searched().max(1)
+        ),
+    }
+}
+
+pub fn run(mut args: Vec<String>, config: &Config) -> Result<(), String> {
+    // wiki4 path [--all-paths [--limit <n, default 100>] | --k <n>] [--avoid <title or pattern>]... [--via <title>]...
+    //            [--max-degree <n>] [--max-length <links>] [--max-pages <n>] [--timeout <seconds>] [--max-memory <MB>]
+    //            <start title> <end title>
+    let format = config.format;
+    let all_paths = take_flag(&mut args, "--all-paths");
+    let k = match take_option(&mut args, "--k").map(|k| k.map(|k| k.parse::<usize>())) {
+        Ok(None) => None,
+        Ok(Some(Ok(k))) if k > 0 => Some(k),
+        _ => return Err("--k needs a number above 0".to_string()),
+    };
+    if all_paths && k.is_some() {
+        return Err("--all-paths and --k can't be used together".to_string());
+    }
+    let avoid = take_options(&mut args, "--avoid")?;
+    let waypoints = take_options(&mut args, "--via")?;
+    if !waypoints.is_empty() && (all_paths || k.is_some()) {
+        return Err("--via can't be used with --all-paths or --k".to_string());
+    }
+    let max_degree = match take_option(&mut args, "--max-degree").map(|d| d.map(|d| d.parse::<i64>())) {
+        Ok(None) => None,
+        Ok(Some(Ok(max_degree))) => Some(max_degree),
+        _ => return Err("--max-degree needs a number".to_string()),
+    };
+    let max_length = match take_option(&mut args, "--max-length").map(|l| l.map(|l| l.parse::<usize>())) {
+        Ok(None) => None,
+        Ok(Some(Ok(max_length))) => Some(max_length),
+        _ => return Err("--max-length needs a number".to_string()),
+    };
+    let max_pages = match take_option(&mut args, "--max-pages").map(|p| p.map(|p| p.parse::<u32>())) {
+        Ok(None) => None,
+        Ok(Some(Ok(max_pages))) => Some(max_pages),
+        _ => return Err("--max-pages needs a number".to_string()),
+    };
+    let timeout = match take_option(&mut args, "--timeout").map(|t| t.map(|t| t.parse::<f64>())) {
+        Ok(None) => None,
+        Ok(Some(Ok(seconds))) if seconds >= 0.0 => Some(Duration::from_secs_f64(seconds)),
+        _ => return Err("--timeout needs a number of seconds".to_string()),
+    };
+    let max_memory = match take_option(&mut args, "--max-memory").map(|m| m.map(|m| m.parse::<usize>())) {
+        Ok(None) => None,
+        Ok(Some(Ok(megabytes))) => Some(megabytes * 1024 * 1024),
+        _ => return Err("--max-memory needs a number of megabytes".to_string()),
+    };
+    let limit = match take_option(&mut args, "--limit").map(|limit| limit.map(|l| l.parse::<usize>())) {
+        Ok(None) => DEFAULT_PATH_LIMIT,
+        Ok(Some(Ok(limit))) if limit > 0 => limit,
+        _ => return Err("--limit needs a number above 0".to_string()),
+    };
+    if let Some(flag) = unknown_flag(&args) {
+        return Err(format!("Unknown option '{}'", flag));
+    }
+    let [starting_at, searching_for] = match <[String; 2]>::try_from(args) {
+        Ok(titles) => titles,
+        Err(_) => return Err("Usage: wiki4 path [options] <start title> <end title>".to_string()),
+    };
+
+    let start_time = Instant::now();
+
+    let db = open_db(config)?;
+
+    // A missing page's id is still a valid hash, so a typo would otherwise search everything reachable.
+    // Searching from a redirect's target saves a step and lets the path show the redirect as a note.
+    // The start, each waypoint in order and the end
+    let stop_titles: Vec<&String> = [&starting_at].into_iter().chain(&waypoints).chain([&searching_for]).collect();
+    let mut stops = Vec::with_capacity(stop_titles.len());
+    for title in &stop_titles {
+        stops.push(find_page(&db, title, format)?.id);
+    }
+    let (start_id, end_id) = (stops[0], *stops.last().unwrap());
+
+    let mut exclusions = build_exclusions(&db, &avoid, format)?;
+    if let Some(i) = stops.iter().position(|stop| exclusions.pages.contains(stop)) {
+        return Err(format!("'{}' is one of the pages to avoid", stop_titles[i]));
+    }
+    if let Some(max_degree) = max_degree {
+        exclude_hubs(&db, &mut exclusions, max_degree, &stops, format)?;
+    }
+    // Databases from before link text was kept have nothing beyond each link's ends
+    let details = has_column(&db, "links", "section").then_some(&db);
+
+    let mut graph = load_graph(&db, config)?;
+
+    for leg in stops.windows(2) {
+        if let Some(reason) = impossible_path(&db, graph.as_mut(), leg[0], leg[1])? {
+            match format {
+                Format::Text => {
+                    println!("No path: {}", reason);
+                    println!("Completed in {}", start_time.elapsed().hhmmssxxx());
+                }
+                Format::Json => println!(
+                    "{{\"result\":\"no_path\",\"start\":{},\"end\":{},\"reason\":{},\"paths\":[]}}",
+                    json_string(&graph.title(start_id)?),
+                    json_string(&graph.title(end_id)?),
+                    json_string(&reason)
+                ),
+            }
+            return Ok(());
+        }
+    }
+
+    let mut budget = Budget::new();
+    budget.max_pages = max_pages;
+    budget.timeout = timeout;
+    budget.max_memory = max_memory;
+    budget.progress = format == Format::Text;
+    let handler_flag = budget.interrupted.clone();
+    // The first Ctrl-C stops the search where it is, a second one stops the program
+    ctrlc::set_handler(move || {
+        if handler_flag.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+    }).unwrap();
+
+    // Everything the search found, for JSON
+    let mut paths: Vec<Vec<i64>> = Vec::new();
+    let mut total: Option<u128> = None;
+    // Why the search (or the search for more paths) gave up, with the longest path it could look for
+    let mut gave_up: Option<(GaveUp, Option<usize>)> = None;
+    // Pages seen and still to search by the first search - waypoint legs are each searched afresh
+    let mut seen = None;
+
+    // With waypoints each leg is searched on its own and the shortest paths joined
+    if stops.len() > 2 {
+        let search = search::path_via(graph.as_mut(), &stops, &exclusions, max_length, &mut budget)?;
+        match (search.outcome, search.leg) {
+            (Outcome::Found(path), _) => {
+                if format == Format::Text {
+                    println!("Final path:");
+                    println!("{}", fmt_path(&path.pages, graph.as_mut(), details)?);
+                }
+                paths.push(path.pages);
+            }
+            (Outcome::NoPath, Some((from, to, _))) => {
+                format.note(format!("No path from '{}' to '{}'", graph.title(from)?, graph.title(to)?));
+            }
+            (Outcome::GaveUp(leg_gave_up), Some((from, to, leg_length))) => {
+                format.note(format!("Searching from '{}' to '{}'", graph.title(from)?, graph.title(to)?));
+                if format == Format::Text {
+                    print_gave_up(&leg_gave_up, leg_length);
+                }
+                gave_up = Some((leg_gave_up, leg_length));
+            }
+            _ => {}
+        }
+    }
+    else if all_paths {
+        let search = search::all_shortest_paths(graph.as_mut(), start_id, end_id, &exclusions, max_length, limit, &mut budget)?;
+        seen = Some((search.seen, search.frontier));
+        match search.outcome {
+            Outcome::NoPath => {}
+            Outcome::GaveUp(search_gave_up) => gave_up = Some((search_gave_up, max_length)),
+            Outcome::Found(found) => {
+                total = Some(found.total);
+                paths = found.paths.into_iter().map(|path| path.pages).collect();
+            }
+        }
+
+        if let (Format::Text, Some(total)) = (format, total) {
+            println!(
+                "Found {} shortest {} of {}{}:",
+                total.to_formatted_string(&Locale::en),
+                if total == 1 { "path" } else { "paths" },
+                fmt_links(search::links(&paths[0], graph.as_mut())?),
+                if (paths.len() as u128) < total { format!(", showing the first {}", paths.len()) } else { String::new() }
+            );
+            for (i, path) in paths.iter().enumerate() {
+                println!("Path {}:", i + 1);
+                println!("{}", fmt_path(path, graph.as_mut(), details)?);
+            }
+        }
+    }
+    else if let Some(k) = k {
+        let search = search::k_shortest_paths(graph.as_mut(), start_id, end_id, k, &exclusions, max_length, &mut budget)?;
+        seen = Some((search.seen, search.frontier));
+        match search.outcome {
+            Outcome::NoPath => {}
+            Outcome::GaveUp(search_gave_up) => gave_up = Some((search_gave_up, max_length)),
+            Outcome::Found(found) => {
+                paths = found.paths.into_iter().map(|path| path.pages).collect();
+                gave_up = found.gave_up.map(|found_gave_up| (found_gave_up, max_length));
+
+                if format == Format::Text {
+                    if let Some((gave_up, max_length)) = &gave_up {
+                        print_gave_up(gave_up, *max_length);
+                        println!("Paths found before giving up:");
+                    }
+                    else if paths.len() < k {
+                        println!("Only {} {} without repeated pages:", paths.len(), if paths.len() == 1 { "path goes" } else { "paths go" });
+                    }
+                    for (i, path) in paths.iter().enumerate() {
+                        println!("Path {} ({}):", i + 1, fmt_links(search::links(path, graph.as_mut())?));
+                        println!("{}", fmt_path(path, graph.as_mut(), details)?);
+                    }
+                }
+            }
+        }
+    }
+    else {
+        let search = search::shortest_path(graph.as_mut(), start_id, end_id, &exclusions, max_length, &mut budget)?;
+        seen = Some((search.seen, search.frontier));
+        match search.outcome {
+            Outcome::NoPath => {}
+            Outcome::GaveUp(search_gave_up) => gave_up = Some((search_gave_up, max_length)),
+            Outcome::Found(path) => {
+                if format == Format::Text {
+                    println!("Final path:");
+                    println!("{}", fmt_path(&path.pages, graph.as_mut(), details)?);
+                }
+                paths.push(path.pages);
+            }
+        }
+    }
+
+    match format {
+        Format::Text => {
+            // Paths found before giving up have been printed with the reason already
+            if paths.is_empty() && stops.len() == 2 {
+                match &gave_up {
+                    Some((gave_up, max_length)) => print_gave_up(gave_up, *max_length),
+                    None => println!("No more pages!"),
+                }
+            }
+            print_summary(start_time, &budget, seen);
+        }
+        Format::Json => {
+            let result = match (&gave_up, paths.is_empty()) {
+                (Some(_), true) => "gave_up",
+                (_, false) => "found",
+                (None, true) => "no_path",
+            };
+            let mut fields = vec![
+                format!("\"result\":{}", json_string(result)),
+                format!("\"start\":{}", json_string(&graph.title(start_id)?)),
+                format!("\"end\":{}", json_string(&graph.title(end_id)?)),
+            ];
+            if let Some(total) = total {
+                fields.push(format!("\"total\":{}", total));
+            }
+            let paths = paths.iter().map(|path| json_path(path, graph.as_mut(), details)).collect::<Result<Vec<_>, _>>()?;
+            fields.push(format!("\"paths\":[{}]", paths.join(",")));
+            if let Some((gave_up, max_length)) = &gave_up {
+                fields.push(format!(
+                    "\"gave_up\":{{\"reason\":{},\"depth\":{},\"seen\":{},\"open_set\":{}}}",
+                    json_string(&gave_up_reason(gave_up, *max_length)),
+                    gave_up.depth,
+                    gave_up.seen,
+                    gave_up.frontier
+                ));
+            }
+            fields.push(format!("\"pages_searched\":{}", budget.pages_searched()));
+            if let Some((seen, frontier)) = seen {
+                fields.push(format!("\"seen\":{},\"open_set\":{}", seen, frontier));
+            }
+            fields.push(format!("\"seconds\":{}", start_time.elapsed().as_secs_f64()));
+            println!("{{{}}}", fields.join(","));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiki_4::builder::Builder;
+    use wiki_4::graph::SqliteGraph;
+    use wiki_4::page_id::page_id;
+    use wiki_4::wikitext::WikiLink;
+
+    /// A <-> B, which both link to C, D <-> E, which links to A, and F, which only links to A
+    fn database() -> Connection {
+        let mut builder = Builder::new(":memory:", 10, 100, 100, false).unwrap();
+        for (title, targets) in [("A", &["B", "C"][..]), ("B", &["A", "C"]), ("C", &[]), ("D", &["E", "A"]), ("E", &["D"]), ("F", &["A"])] {
+            let links = targets.iter()
+                .map(|target| WikiLink { text: target.to_string(), target: target.to_string(), section: None, offset: 0 })
+                .collect();
+            builder.cache(title.to_string(), links, false).unwrap();
+        }
+        builder.finish(false).unwrap()
+    }
+
+    fn reason(db: &Connection, start: &str, end: &str) -> Option<String> {
+        let mut graph = SqliteGraph::new(db).unwrap();
+        impossible_path(db, &mut graph, page_id(start), page_id(end)).unwrap()
+    }
+
+    #[test]
+    fn impossible_paths() {
+        let db = database();
+        assert_eq!(reason(&db, "C", "A").as_deref(), Some("'C' doesn't link to any pages"));
+        assert_eq!(reason(&db, "A", "F").as_deref(), Some("no pages link to 'F'"));
+        // Without components only the ends can be checked
+        assert_eq!(reason(&db, "A", "D"), None);
+        assert_eq!(reason(&db, "C", "C"), None);
+
+        // Numbered the way wiki4 components numbers them
+        db.execute_batch("ALTER TABLE pages ADD COLUMN component INTEGER; ALTER TABLE pages ADD COLUMN component_size INTEGER").unwrap();
+        for (title, component, size) in [("C", 0, 1), ("A", 1, 2), ("B", 1, 2), ("D", 2, 2), ("E", 2, 2), ("F", 3, 1)] {
+            db.execute("UPDATE pages SET component = ?, component_size = ? WHERE id = ?", (component, size, page_id(title))).unwrap();
+        }
+        assert_eq!(
+            reason(&db, "A", "D").as_deref(),
+            Some("'D' is in a group of 2 pages that no chain of links from 'A' (in a group of 2) leads into")
+        );
+        assert_eq!(reason(&db, "D", "C"), None);
+        assert_eq!(reason(&db, "A", "B"), None);
+    }
+}
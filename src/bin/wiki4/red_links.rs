@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+use hhmmss::Hhmmss;
+use num_format::{Locale, ToFormattedString};
+use wiki_4::args::{take_flag, take_option, unknown_flag};
+use wiki_4::config::Config;
+use crate::open_db;
+
+const DEFAULT_TOP: usize = 50;
+
+pub fn run(mut args: Vec<String>, config: &Config) -> Result<(), String> {
+    // wiki4 red-links [--top <n>] [--output <tsv path>] [--prune]
+    let top = match take_option(&mut args, "--top").map(|t| t.map(|t| t.parse::<usize>())) {
+        Ok(None) => DEFAULT_TOP,
+        Ok(Some(Ok(top))) => top,
+        _ => return Err("--top needs a number".to_string()),
+    };
+    let output_path = take_option(&mut args, "--output")?;
+    let prune = take_flag(&mut args, "--prune");
+    if let Some(flag) = unknown_flag(&args) {
+        return Err(format!("Unknown option '{}'", flag));
+    }
+
+    let start = Instant::now();
+
+    let conn = open_db(config)?;
+
+    // Databases from before link titles were kept can still be counted, just not named
+    let has_titles: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'red_links')",
+        [],
+        |row| row.get(0)
+    ).unwrap();
+    if !has_titles {
+        println!("Database has no red_links table - rebuild it with wiki4 build to see the titles of missing pages");
+    }
+
+    println!("Finding links to missing pages");
+    let total_links: i64 = conn.query_row("SELECT COUNT(*) FROM links", [], |row| row.get(0)).unwrap();
+
+    let title_column = if has_titles { "(SELECT title FROM red_links WHERE id = destination_id)" } else { "NULL" };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT destination_id, COUNT(*) AS in_links, {} FROM links
+            WHERE NOT EXISTS(SELECT 1 FROM pages WHERE id = destination_id)
+            GROUP BY destination_id
+            ORDER BY in_links DESC",
+        title_column
+    )).unwrap();
+    let red_links: Vec<(i64, i64, Option<String>)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect();
+    drop(stmt);
+
+    let dangling: i64 = red_links.iter().map(|(_, in_links, _)| in_links).sum();
+    println!(
+        "{} of {} links ({:.2}%) point at {} missing pages [{}]",
+        dangling.to_formatted_string(&Locale::en),
+        total_links.to_formatted_string(&Locale::en),
+        100.0 * dangling as f64 / total_links.max(1) as f64,
+        red_links.len().to_formatted_string(&Locale::en),
+        start.elapsed().hhmmss()
+    );
+
+    println!("Most linked missing pages:");
+    for (rank, (id, in_links, title)) in red_links.iter().take(top).enumerate() {
+        match title {
+            Some(title) => println!("{:>6}. {} ({} links)", rank + 1, title, in_links.to_formatted_string(&Locale::en)),
+            None => println!("{:>6}. <unknown title, id {}> ({} links)", rank + 1, id, in_links.to_formatted_string(&Locale::en)),
+        }
+    }
+
+    if let Some(output_path) = output_path {
+        let mut writer = BufWriter::new(File::create(&output_path).unwrap());
+        writeln!(writer, "title\tid\tin_links").unwrap();
+        for (id, in_links, title) in &red_links {
+            writeln!(writer, "{}\t{}\t{}", title.as_deref().unwrap_or(""), id, in_links).unwrap();
+        }
+        writer.flush().unwrap();
+        println!("Wrote the full ranking to '{}'", output_path);
+    }
+
+    if prune {
+        println!("Pruning links to missing pages");
+        let pruned = conn.execute(
+            "DELETE FROM links WHERE NOT EXISTS(SELECT 1 FROM pages WHERE id = destination_id)",
+            ()
+        ).unwrap();
+        println!("Pruned {} links - re-run wiki4 export if you use a graph snapshot", pruned.to_formatted_string(&Locale::en));
+    }
+
+    println!("Completed in {}", start.elapsed().hhmmss());
+    Ok(())
+}
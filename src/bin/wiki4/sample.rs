@@ -0,0 +1,139 @@
+use std::fs;
+use std::io::Write;
+use std::time::Instant;
+use hhmmss::Hhmmss;
+use num_format::{Locale, ToFormattedString};
+use rusqlite::Connection;
+use wiki_4::args::{take_option, unknown_flag};
+use wiki_4::config::{json_string, Config, Format};
+use wiki_4::display::{fmt_path, json_path};
+use wiki_4::lookup;
+use wiki_4::search::{self, Budget, Exclusions, Outcome};
+use crate::{has_column, load_graph, open_db};
+
+/// Title of a random article, other than `other`
+fn random_article(db: &Connection, other: Option<&str>) -> String {
+    loop {
+        let (title, is_redirect): (String, bool) = db.prepare("SELECT title, is_redirect FROM pages ORDER BY RANDOM() LIMIT 1").unwrap()
+            .query_row([], |row| {
+                Ok((row.get(0).unwrap(), row.get(1).unwrap()))
+            }
+            ).unwrap();
+
+        if !is_redirect && Some(title.as_str()) != other {
+            return title;
+        }
+    }
+}
+
+pub fn run(mut args: Vec<String>, config: &Config) -> Result<(), String> {
+    // wiki4 sample [--count <searches>]
+    let count = match take_option(&mut args, "--count").map(|c| c.map(|c| c.parse::<usize>())) {
+        Ok(None) => None,
+        Ok(Some(Ok(count))) => Some(count),
+        _ => return Err("--count needs a number".to_string()),
+    };
+    if let Some(flag) = unknown_flag(&args) {
+        return Err(format!("Unknown option '{}'", flag));
+    }
+    let format = config.format;
+
+    let db = open_db(config)?;
+    let details = has_column(&db, "links", "section").then_some(&db);
+    let mut graph = load_graph(&db, config)?;
+
+    let mut searches_total = 0usize;
+    let mut searches = 0usize;
+    let mut not_found = 0usize;
+
+    while count.is_none_or(|count| searches + not_found < count) {
+        let random_start = Instant::now();
+        if format == Format::Text {
+            print!("Selecting Random... ");
+        }
+
+        let starting_at = random_article(&db, None);
+        let searching_for = random_article(&db, Some(&starting_at));
+
+        if format == Format::Text {
+            println!("{:?} - {} -> {}", random_start.elapsed(), starting_at, searching_for);
+        }
+
+        // Both titles were just read from pages, so they are always found
+        let start_id = lookup::find_page(&db, &starting_at).unwrap().unwrap().id;
+        let end_id = lookup::find_page(&db, &searching_for).unwrap().unwrap().id;
+
+        let start_time = Instant::now();
+
+        let mut budget = Budget::new();
+        budget.progress = format == Format::Text;
+        let search = search::shortest_path(graph.as_mut(), start_id, end_id, &Exclusions::default(), None, &mut budget)?;
+
+        let path = match search.outcome {
+            Outcome::Found(path) => {
+                // Pages shown, which leaves out redirects passed through
+                searches_total += path.links(graph.as_mut())? + 1;
+                searches += 1;
+                Some(path)
+            }
+            _ => {
+                let mut file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&config.not_found)
+                    .unwrap();
+
+                writeln!(file, "{} -> {}", starting_at, searching_for).unwrap();
+
+                not_found += 1;
+                None
+            }
+        };
+        let average = (searches_total as f64) / (searches as f64);
+
+        match format {
+            Format::Text => {
+                match &path {
+                    Some(path) => {
+                        println!("Final path:");
+                        println!("{}", fmt_path(&path.pages, graph.as_mut(), details)?);
+                    }
+                    None => println!("No more pages!"),
+                }
+
+                println!("Completed in {}", start_time.elapsed().hhmmssxxx());
+                println!(
+                    "Pages searched: {} [{:?}/page] | Cache size: {} | Open set size: {}",
+                    budget.pages_searched().to_formatted_string(&Locale::en),
+                    start_time.elapsed() / budget.pages_searched().max(1),
+                    search.seen.to_formatted_string(&Locale::en),
+                    search.frontier.to_formatted_string(&Locale::en),
+                );
+
+                println!("Rolling Average: Total: {} Searched: {} Average: {} Not Found: {}", searches_total, searches, average, not_found)
+            }
+            Format::Json => {
+                let json = match &path {
+                    Some(path) => json_path(&path.pages, graph.as_mut(), details)?,
+                    None => "null".to_string(),
+                };
+                println!(
+                    "{{\"start\":{},\"end\":{},\"found\":{},\"path\":{},\"pages_searched\":{},\"seconds\":{},\"searched\":{},\"not_found\":{},\"average\":{}}}",
+                    json_string(&starting_at),
+                    json_string(&searching_for),
+                    path.is_some(),
+                    json,
+                    budget.pages_searched(),
+                    start_time.elapsed().as_secs_f64(),
+                    searches,
+                    not_found,
+                    // NaN isn't valid JSON
+                    if searches > 0 { average.to_string() } else { "null".to_string() }
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
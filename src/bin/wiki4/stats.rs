@@ -0,0 +1,122 @@
+use std::time::Instant;
+use hhmmss::Hhmmss;
+use num_format::{Locale, ToFormattedString};
+use rusqlite::Connection;
+use wiki_4::args::{take_option, unknown_flag};
+use wiki_4::config::{json_string, Config, Format};
+use crate::open_db;
+
+const DEFAULT_TOP: usize = 20;
+
+/// The `top` articles (redirects aside) with the highest `column`
+fn top_pages(conn: &Connection, column: &str, top: usize) -> Vec<(String, i64)> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT title, {0} FROM pages WHERE NOT is_redirect ORDER BY {0} DESC LIMIT ?",
+        column
+    )).unwrap();
+    stmt.query_map([top as i64], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(|row| row.unwrap()).collect()
+}
+
+fn print_top(pages: &[(String, i64)]) {
+    for (rank, (title, count)) in pages.iter().enumerate() {
+        println!("{:>6}. {} ({})", rank + 1, title, count.to_formatted_string(&Locale::en));
+    }
+}
+
+fn json_top(pages: &[(String, i64)]) -> String {
+    let pages: Vec<String> = pages.iter()
+        .map(|(title, count)| format!("{{\"title\":{},\"links\":{}}}", json_string(title), count))
+        .collect();
+    format!("[{}]", pages.join(","))
+}
+
+fn count(conn: &Connection, query: &str) -> i64 {
+    conn.query_row(query, [], |row| row.get(0)).unwrap()
+}
+
+pub fn run(mut args: Vec<String>, config: &Config) -> Result<(), String> {
+    // wiki4 stats [--top <n>]
+    let top = match take_option(&mut args, "--top").map(|t| t.map(|t| t.parse::<usize>())) {
+        Ok(None) => DEFAULT_TOP,
+        Ok(Some(Ok(top))) => top,
+        _ => return Err("--top needs a number".to_string()),
+    };
+    if let Some(flag) = unknown_flag(&args) {
+        return Err(format!("Unknown option '{}'", flag));
+    }
+    let format = config.format;
+
+    let start = Instant::now();
+
+    format.note("Opening connection");
+    let conn = open_db(config)?;
+
+    format.note("Adding columns");
+    for column in ["in_degree", "out_degree"] {
+        // Left over from a previous run
+        let _ = conn.execute(&format!("ALTER TABLE pages DROP COLUMN {}", column), ());
+        conn.execute(&format!("ALTER TABLE pages ADD COLUMN {} INTEGER NOT NULL DEFAULT 0", column), ()).unwrap();
+    }
+
+    // Every link is counted, including ones to pages that don't exist - `wiki4 red-links --prune` removes those
+    format.note("Counting links out of each page");
+    let updated = conn.execute(
+        "UPDATE pages SET out_degree = counts.n
+            FROM (SELECT source_id, COUNT(*) AS n FROM links GROUP BY source_id) AS counts
+            WHERE pages.id = counts.source_id",
+        ()
+    ).unwrap();
+    format.note(format!("Counted the links out of {} pages in {}", updated.to_formatted_string(&Locale::en), start.elapsed().hhmmss()));
+
+    format.note("Counting links into each page");
+    let updated = conn.execute(
+        "UPDATE pages SET in_degree = counts.n
+            FROM (SELECT destination_id, COUNT(*) AS n FROM links GROUP BY destination_id) AS counts
+            WHERE pages.id = counts.destination_id",
+        ()
+    ).unwrap();
+    format.note(format!("Counted the links into {} pages in {}", updated.to_formatted_string(&Locale::en), start.elapsed().hhmmss()));
+
+    let articles = count(&conn, "SELECT COUNT(*) FROM pages WHERE NOT is_redirect");
+    let redirects = count(&conn, "SELECT COUNT(*) FROM pages WHERE is_redirect");
+    let links = count(&conn, "SELECT COUNT(*) FROM links");
+    // Titles linked to that no page has, kept by builds since red links were recorded
+    let has_red_links: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'red_links')",
+        [],
+        |row| row.get(0)
+    ).unwrap();
+    let missing = has_red_links.then(|| count(&conn, "SELECT COUNT(*) FROM red_links"));
+    let linked_to = top_pages(&conn, "in_degree", top);
+    let linking = top_pages(&conn, "out_degree", top);
+
+    match format {
+        Format::Text => {
+            println!(
+                "{} articles, {} redirects and {} links",
+                articles.to_formatted_string(&Locale::en),
+                redirects.to_formatted_string(&Locale::en),
+                links.to_formatted_string(&Locale::en)
+            );
+            if let Some(missing) = missing {
+                println!("{} titles are linked to without a page", missing.to_formatted_string(&Locale::en));
+            }
+            println!("Most linked to articles:");
+            print_top(&linked_to);
+            println!("Articles with the most links:");
+            print_top(&linking);
+        }
+        Format::Json => println!(
+            "{{\"articles\":{},\"redirects\":{},\"links\":{},\"missing_pages\":{},\"most_linked_to\":{},\"most_linking\":{}}}",
+            articles,
+            redirects,
+            links,
+            missing.map_or("null".to_string(), |missing| missing.to_string()),
+            json_top(&linked_to),
+            json_top(&linking)
+        ),
+    }
+
+    format.note(format!("Completed in {}", start.elapsed().hhmmss()));
+    Ok(())
+}
@@ -0,0 +1,23 @@
+//! Small link graphs for testing the commands that analyse the whole graph
+
+use wiki_4::builder::Builder;
+use wiki_4::csr::{IndexedGraph, LinkTable};
+use wiki_4::page_id::page_id;
+use wiki_4::wikitext::WikiLink;
+
+/// Graph of the pages in `links`, each with the pages it links to
+pub fn graph(links: &[(&str, &[&str])]) -> IndexedGraph {
+    let mut builder = Builder::new(":memory:", 10, 100, 100, false).unwrap();
+    for (title, targets) in links {
+        let targets = targets.iter()
+            .map(|target| WikiLink { text: target.to_string(), target: target.to_string(), section: None, offset: 0 })
+            .collect();
+        builder.cache(title.to_string(), targets, false).unwrap();
+    }
+    IndexedGraph::Table(LinkTable::read(&builder.finish(false).unwrap()).unwrap())
+}
+
+/// Index in `graph` of the page titled `title`
+pub fn index(graph: &IndexedGraph, title: &str) -> u32 {
+    (0..graph.node_count() as u32).find(|&index| graph.id(index) == page_id(title)).unwrap()
+}
@@ -0,0 +1,755 @@
+//! Writes the pages and links of a dump into the database's tables
+//!
+//! Pages are passed to `Builder::cache` one at a time (after `parse_page`) and written in batches;
+//! `Builder::finish` then indexes the links, resolves hash collisions and redirects and trims the red
+//! links.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+use rusqlite::{Connection, ToSql};
+use rusqlite::types::Null;
+use crate::dump::DumpPage;
+use crate::namespace::Namespaces;
+use crate::page_id::{self, page_id, salted_page_id, title_check};
+use crate::title;
+use crate::wikitext::{self, LinkOptions, WikiLink};
+
+/// Which namespaces' pages and link targets are kept in the graph
+pub struct NamespaceFilter {
+    namespaces: Namespaces,
+    included: HashSet<i64>,
+}
+
+impl NamespaceFilter {
+    /// Parses a comma separated list of namespace keys or names, e.g. `0,Portal`
+    pub fn new(namespaces: Namespaces, list: &str) -> Result<Self, String> {
+        let mut included = HashSet::new();
+        for name in list.split(',') {
+            match namespaces.parse(name) {
+                Some(key) => { included.insert(key); }
+                None => return Err(format!("Unknown namespace '{}'", name.trim())),
+            }
+        }
+
+        Ok(Self {
+            namespaces,
+            included,
+        })
+    }
+
+    pub fn includes_page(&self, namespace: i64) -> bool {
+        self.included.contains(&namespace)
+    }
+
+    pub fn includes_link(&self, target: &str) -> bool {
+        self.included.contains(&self.namespaces.classify(target))
+    }
+
+    pub fn namespaces(&self) -> &Namespaces {
+        &self.namespaces
+    }
+}
+
+/// Title, links and whether the page is a redirect, or `None` if the page isn't part of the graph.
+/// Fails with the reason if the page's links can't be read.
+pub fn parse_page(page: DumpPage, filter: &NamespaceFilter, link_options: &LinkOptions) -> Result<Option<(String, Vec<WikiLink>, bool)>, String> {
+    if !filter.includes_page(page.namespace) {
+        return Ok(None);
+    }
+
+    let (links, is_redirect) = match page.redirect {
+        Some(redirect) => (get_redirect_link(&redirect, filter), true),
+        None => get_links_from_body(page.text, &page.title, filter, link_options)?,
+    };
+
+    Ok(Some((page.title, links, is_redirect)))
+}
+
+/// Where a link is in its page, recorded with `--link-details`
+struct LinkPosition {
+    /// Section heading, empty in the lead
+    section: String,
+    /// Index of the link among the page's links
+    position: u32,
+    /// Byte offset in the page's wikitext
+    offset: u32,
+}
+
+/// Tens of millions of these are buffered at a time, so the rarely present parts are boxed
+struct LinkRow {
+    source_id: i64,
+    destination_id: i64,
+    destination_check: u32,
+    /// Link text, if it isn't the destination's title (or `link_details` is on)
+    anchor: Option<Box<str>>,
+    position: Option<Box<LinkPosition>>,
+}
+
+impl LinkRow {
+    fn params(&self) -> [&dyn ToSql; 7] {
+        let (section, position, offset): (&dyn ToSql, &dyn ToSql, &dyn ToSql) = match &self.position {
+            Some(p) => (&p.section, &p.position, &p.offset),
+            None => (&Null, &Null, &Null),
+        };
+        [&self.source_id, &self.destination_id, &self.destination_check, &self.anchor, section, position, offset]
+    }
+}
+
+pub struct Builder {
+    conn: Connection,
+    batch_size: usize,
+    pages_insert_threshold: usize,
+    links_insert_threshold: usize,
+    pages_to_insert: Vec<(i64, String, bool)>,
+    links_to_insert: Vec<LinkRow>,
+    /// Record each link's section and position as well as its text
+    link_details: bool,
+    /// `title_check` of the page given each id so far
+    page_checks: HashMap<i64, u32>,
+    /// Original id, title that was moved and the id it was moved to
+    collisions: Vec<(i64, String, i64)>,
+    /// Link targets whose title has been kept in case no page turns out to have it
+    recorded_targets: HashSet<i64>,
+    target_titles_to_insert: Vec<(i64, String)>,
+    /// Hashes a title to its unsalted id - only replaced by tests, to force collisions
+    page_id: fn(&str) -> i64,
+    /// Told what the builder is doing - ignored unless `report_progress` says otherwise
+    progress: Box<dyn FnMut(&str)>,
+}
+
+impl Builder {
+    /// Opens (or creates) the database at `path`, replacing any tables a previous build left there
+    pub fn new(path: &str, batch_size: usize, pages_insert_threshold: usize, links_insert_threshold: usize, link_details: bool) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+
+        conn.execute_batch(
+            "PRAGMA journal_mode = OFF;
+              PRAGMA synchronous = OFF;
+              PRAGMA cache_size = 1000000;
+              PRAGMA locking_mode = EXCLUSIVE;
+              PRAGMA temp_store = MEMORY;",
+        )?;
+
+        conn.execute("DROP TABLE IF EXISTS pages", ())?;
+
+        conn.execute("DROP TABLE IF EXISTS links", ())?;
+
+        conn.execute("DROP TABLE IF EXISTS metadata", ())?;
+
+        conn.execute("DROP TABLE IF EXISTS hash_collisions", ())?;
+
+        conn.execute("DROP TABLE IF EXISTS redirects", ())?;
+
+        conn.execute("DROP TABLE IF EXISTS red_links", ())?;
+
+        // Title index built by the path search, which would go stale
+        conn.execute("DROP TABLE IF EXISTS page_titles", ())?;
+
+        page_id::record_scheme(&conn)?;
+        page_id::record_build_id(&conn)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS pages (
+                id INTEGER PRIMARY KEY,
+                title TEXT,
+                is_redirect BOOLEAN
+            )",
+           ()
+        )?;
+
+        // Title links were written with for each destination - trimmed to the ones no page has once
+        // every page has been written
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS red_links (
+                id INTEGER PRIMARY KEY,
+                title TEXT
+            )",
+            ()
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS links (
+                source_id INTEGER,
+                destination_id INTEGER,
+                destination_check INTEGER,
+                redirect_id INTEGER,
+                anchor TEXT,
+                section TEXT,
+                position INTEGER,
+                offset INTEGER,
+                PRIMARY KEY(source_id, destination_id, destination_check)
+            )",
+           ()
+        )?;
+
+        Ok(Self {
+            conn,
+            batch_size,
+            pages_insert_threshold,
+            links_insert_threshold,
+            pages_to_insert: Vec::with_capacity(pages_insert_threshold + (pages_insert_threshold / 100)),
+            links_to_insert: Vec::with_capacity(links_insert_threshold + (links_insert_threshold / 100)),
+            link_details,
+            page_checks: HashMap::new(),
+            collisions: Vec::new(),
+            recorded_targets: HashSet::new(),
+            target_titles_to_insert: Vec::new(),
+            page_id,
+            progress: Box::new(|_| {}),
+        })
+    }
+
+    /// Passes a line to `report` as each step of the build starts and finishes
+    pub fn report_progress(&mut self, report: impl FnMut(&str) + 'static) {
+        self.progress = Box::new(report);
+    }
+
+    pub fn write_pages_to_db(&mut self) -> rusqlite::Result<()> {
+        if self.pages_to_insert.is_empty() {
+            (self.progress)("Cancelling db write as page cache is empty");
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        (self.progress)(&format!("Writing {} pages to database", self.pages_to_insert.len()));
+        let tx = self.conn.transaction()?;
+
+        let mut cached_statement =
+            tx.prepare_cached(
+                format!("INSERT INTO pages VALUES {}", " (?, ?, ?),".repeat(self.batch_size - 1) + " (?, ?, ?)")
+                    .as_str())?;
+
+        let mut individual_cached_statement =
+            tx.prepare_cached("INSERT INTO pages VALUES (?, ?, ?)")?;
+
+        let mut params = Vec::with_capacity(self.batch_size * 3);
+        let (batchable, non_batchable) = self.pages_to_insert.split_at(
+            self.pages_to_insert.len() - (self.pages_to_insert.len() % self.batch_size)
+        );
+
+        let mut count = 0;
+        for data in batchable {
+            params.push(&data.0 as &dyn ToSql);
+            params.push(&data.1 as &dyn ToSql);
+            params.push(&data.2 as &dyn ToSql);
+            count += 1;
+            if count == self.batch_size {
+                if let Err(e) = cached_statement.execute(&*params) {
+                    (self.progress)(&format!("Database pages batch failed due to error - retrying one at a time: {:?}", e));
+
+                    for params in params.chunks(3) {
+                        individual_cached_statement.execute(params)?;
+                    }
+                }
+                params = Vec::with_capacity(self.batch_size * 3);
+                count = 0;
+            }
+        }
+
+        for data in non_batchable {
+            individual_cached_statement.execute((&data.0, &data.1, &data.2))?;
+        }
+
+        drop(cached_statement);
+        drop(individual_cached_statement);
+
+        tx.commit()?;
+
+        self.pages_to_insert = Vec::with_capacity(self.pages_insert_threshold + (self.pages_insert_threshold / 100));
+        (self.progress)(&format!("Finished writing pages to database in {:?}", start.elapsed()));
+        Ok(())
+    }
+
+    pub fn write_links_to_db(&mut self) -> rusqlite::Result<()> {
+        if self.links_to_insert.is_empty() {
+            (self.progress)("Cancelling db write as link cache is empty");
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        (self.progress)(&format!("Writing {} links to database", self.links_to_insert.len()));
+        let tx = self.conn.transaction()?;
+
+        let mut cached_statement =
+            tx.prepare_cached(
+                format!(
+                    "INSERT INTO links (source_id, destination_id, destination_check, anchor, section, position, offset) VALUES {}",
+                    " (?, ?, ?, ?, ?, ?, ?),".repeat(self.batch_size - 1) + " (?, ?, ?, ?, ?, ?, ?)"
+                )
+                    .as_str())?;
+
+        let mut individual_cached_statement =
+            tx.prepare_cached(
+                "INSERT INTO links (source_id, destination_id, destination_check, anchor, section, position, offset) VALUES (?, ?, ?, ?, ?, ?, ?)"
+            )?;
+
+        let mut params = Vec::with_capacity(self.batch_size * 7);
+        let (batchable, non_batchable) = self.links_to_insert.split_at(
+            self.links_to_insert.len() - (self.links_to_insert.len() % self.batch_size)
+        );
+
+        let mut count = 0;
+        for data in batchable {
+            params.extend(data.params());
+            count += 1;
+            if count == self.batch_size {
+                if let Err(e) = cached_statement.execute(&*params) {
+                    (self.progress)(&format!("Database links batch failed due to error - retrying one at a time: {:?}", e));
+
+                    for params in params.chunks(7) {
+                        individual_cached_statement.execute(params)?;
+                    }
+                }
+                params = Vec::with_capacity(self.batch_size * 7);
+                count = 0;
+            }
+        }
+
+        for data in non_batchable {
+            individual_cached_statement.execute(data.params())?;
+        }
+
+        drop(cached_statement);
+        drop(individual_cached_statement);
+
+        let mut target_title_statement = tx.prepare_cached("INSERT OR IGNORE INTO red_links VALUES (?, ?)")?;
+        for (id, title) in self.target_titles_to_insert.drain(..) {
+            target_title_statement.execute((id, title))?;
+        }
+        drop(target_title_statement);
+
+        tx.commit()?;
+
+        self.links_to_insert = Vec::with_capacity(self.links_insert_threshold + (self.links_insert_threshold / 100));
+        (self.progress)(&format!("Finished writing links to database in {:?}", start.elapsed()));
+        Ok(())
+    }
+
+    /// Indexes `links` by destination so searches can also walk links backwards from the target
+    pub fn create_backlinks_index(&mut self) -> rusqlite::Result<()> {
+        let start = Instant::now();
+        (self.progress)("Creating backlinks index");
+
+        self.conn.execute("CREATE INDEX IF NOT EXISTS links_destination ON links(destination_id)", ())?;
+
+        (self.progress)(&format!("Finished creating backlinks index in {:?}", start.elapsed()));
+        Ok(())
+    }
+
+    /// Records any titles moved to a salted id by `cache` and points the links meant for them at their
+    /// new id
+    pub fn resolve_collisions(&mut self) -> rusqlite::Result<()> {
+        let start = Instant::now();
+        (self.progress)(&format!("Resolving {} hash collisions", self.collisions.len()));
+
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS hash_collisions (
+                id INTEGER,
+                title TEXT,
+                kept_title TEXT,
+                resolved_id INTEGER
+            )",
+            ()
+        )?;
+
+        let tx = self.conn.transaction()?;
+        let mut kept_title_statement = tx.prepare("SELECT title FROM pages WHERE id = ?")?;
+        let mut insert_statement = tx.prepare("INSERT INTO hash_collisions VALUES (?, ?, ?, ?)")?;
+        let mut update_statement = tx.prepare(
+            "UPDATE links SET destination_id = ? WHERE destination_id = ? AND destination_check = ?"
+        )?;
+
+        for (id, title, resolved_id) in &self.collisions {
+            let kept_title: String = kept_title_statement.query_row([id], |row| row.get(0))?;
+            insert_statement.execute((id, title, &kept_title, resolved_id))?;
+            let moved = update_statement.execute((resolved_id, id, title_check(title)))?;
+            (self.progress)(&format!("'{}' collided with '{}' - moved to {} along with {} links", title, kept_title, resolved_id, moved));
+        }
+
+        drop(kept_title_statement);
+        drop(insert_statement);
+        drop(update_statement);
+        tx.commit()?;
+
+        (self.progress)(&format!("Finished resolving hash collisions in {:?}", start.elapsed()));
+        Ok(())
+    }
+
+    /// Follows every redirect (through double redirects) to the page it ends up at and records it in
+    /// `redirects`. Redirects that loop or lead to a missing page are left out. With `rewrite_links`,
+    /// links to a redirect are pointed straight at its target with the redirect kept in `redirect_id`.
+    pub fn resolve_redirects(&mut self, rewrite_links: bool) -> rusqlite::Result<()> {
+        let start = Instant::now();
+        (self.progress)("Resolving redirects");
+
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS redirects (
+                source_id INTEGER PRIMARY KEY,
+                target_id INTEGER
+            )",
+            ()
+        )?;
+
+        // Redirect to the page it links to and that link's `destination_check`
+        let mut next: HashMap<i64, (i64, u32)> = HashMap::new();
+        {
+            let mut stmt = self.conn.prepare(
+                "SELECT links.source_id, links.destination_id, links.destination_check FROM links
+                    JOIN pages ON pages.id = links.source_id
+                    WHERE pages.is_redirect"
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?;
+            for row in rows {
+                let (source, destination) = row?;
+                next.insert(source, destination);
+            }
+        }
+
+        let tx = self.conn.transaction()?;
+        let mut exists_statement = tx.prepare("SELECT EXISTS(SELECT 1 FROM pages WHERE id = ?)")?;
+        let mut insert_statement = tx.prepare("INSERT INTO redirects VALUES (?, ?)")?;
+
+        let mut resolved: Vec<(i64, i64, u32)> = Vec::with_capacity(next.len());
+        let (mut loops, mut broken) = (0, 0);
+        for (&source, &first) in &next {
+            let mut target = first;
+            let mut chain = vec![source];
+            while let Some(&hop) = next.get(&target.0) {
+                if chain.contains(&target.0) {
+                    break;
+                }
+                chain.push(target.0);
+                target = hop;
+            }
+
+            if chain.contains(&target.0) {
+                loops += 1;
+            }
+            else if !exists_statement.query_row([target.0], |row| row.get::<_, bool>(0))? {
+                broken += 1;
+            }
+            else {
+                insert_statement.execute((source, target.0))?;
+                resolved.push((source, target.0, target.1));
+            }
+        }
+        (self.progress)(&format!("Resolved {} redirects ({} loop, {} lead to missing pages)", resolved.len(), loops, broken));
+
+        if rewrite_links {
+            let mut update_statement = tx.prepare(
+                "UPDATE OR IGNORE links SET destination_id = ?, destination_check = ?, redirect_id = ? WHERE destination_id = ?"
+            )?;
+            let mut moved = 0;
+            for (source, target, check) in &resolved {
+                moved += update_statement.execute((target, check, source, source))?;
+            }
+
+            // Anything still pointing at a resolved redirect already links to its target directly
+            let duplicates = tx.execute("DELETE FROM links WHERE destination_id IN (SELECT source_id FROM redirects)", ())?;
+            (self.progress)(&format!("Pointed {} links at redirect targets, dropping {} that duplicated a direct link", moved, duplicates));
+        }
+
+        drop(exists_statement);
+        drop(insert_statement);
+        tx.commit()?;
+
+        (self.progress)(&format!("Finished resolving redirects in {:?}", start.elapsed()));
+        Ok(())
+    }
+
+    /// Drops the titles kept for link targets that turned out to be pages, leaving `red_links` with
+    /// only the targets no page has
+    pub fn trim_red_links(&mut self) -> rusqlite::Result<()> {
+        let start = Instant::now();
+        (self.progress)("Finding red links");
+
+        self.conn.execute("DELETE FROM red_links WHERE id IN (SELECT id FROM pages)", ())?;
+        let count: i64 = self.conn.query_row("SELECT COUNT(*) FROM red_links", [], |row| row.get(0))?;
+
+        (self.progress)(&format!("Found {} titles linked to without a page in {:?}", count, start.elapsed()));
+        Ok(())
+    }
+
+    /// Id to store a page under, or `None` if a page with the same title key has already been stored.
+    /// A different title whose hash collides is moved to a salted id.
+    fn assign_id(&mut self, title: &str) -> Option<i64> {
+        let id = (self.page_id)(title);
+        let check = title_check(title);
+
+        match self.page_checks.get(&id) {
+            None => {
+                self.page_checks.insert(id, check);
+                Some(id)
+            }
+            Some(&existing) if existing == check => None,
+            Some(_) => {
+                let mut salt = 1;
+                let mut resolved_id = salted_page_id(title, salt);
+                while self.page_checks.contains_key(&resolved_id) {
+                    salt += 1;
+                    resolved_id = salted_page_id(title, salt);
+                }
+
+                self.page_checks.insert(resolved_id, check);
+                self.collisions.push((id, title.to_string(), resolved_id));
+                Some(resolved_id)
+            }
+        }
+    }
+
+    pub fn cache(&mut self, title: String, links: Vec<WikiLink>, is_redirect: bool) -> rusqlite::Result<()> {
+        let title = title::normalize(&title);
+        let Some(title_hash) = self.assign_id(&title) else {
+            (self.progress)(&format!("Skipping duplicate page '{}'", title));
+            return Ok(());
+        };
+
+        self.pages_to_insert.push((title_hash, title, is_redirect));
+
+        // Destinations already linked to by the page - only the first link to each is kept
+        let mut linked = HashSet::new();
+        for (position, link) in links.into_iter().enumerate() {
+            let destination_id = (self.page_id)(&link.target);
+            let destination_check = title_check(&link.target);
+            if !linked.insert((destination_id, destination_check)) {
+                continue;
+            }
+            // Pages already seen certainly aren't red links
+            if !self.page_checks.contains_key(&destination_id) && self.recorded_targets.insert(destination_id) {
+                self.target_titles_to_insert.push((destination_id, title::normalize(&link.target)));
+            }
+
+            let mut row = LinkRow {
+                source_id: title_hash,
+                destination_id,
+                destination_check,
+                anchor: None,
+                position: None,
+            };
+            if self.link_details {
+                row.anchor = Some(link.text.into());
+                row.position = Some(Box::new(LinkPosition {
+                    section: link.section.unwrap_or_default(),
+                    position: position as u32,
+                    offset: link.offset as u32,
+                }));
+            }
+            // Most links show the title they link to, so otherwise only the others are stored
+            else if link.text != title::normalize(&link.target) {
+                row.anchor = Some(link.text.into());
+            }
+            self.links_to_insert.push(row);
+        }
+
+        if self.pages_to_insert.len() >= self.pages_insert_threshold {
+            self.write_pages_to_db()?;
+        }
+        if self.links_to_insert.len() >= self.links_insert_threshold {
+            self.write_links_to_db()?;
+        }
+        Ok(())
+    }
+
+    /// Writes whatever is still cached and runs the steps that need every page: indexing links by
+    /// destination, resolving collisions and redirects (see `resolve_redirects`) and trimming red links.
+    /// Returns the connection to the finished database.
+    pub fn finish(mut self, rewrite_redirects: bool) -> rusqlite::Result<Connection> {
+        self.write_pages_to_db()?;
+        self.write_links_to_db()?;
+        self.create_backlinks_index()?;
+        self.resolve_collisions()?;
+        self.resolve_redirects(rewrite_redirects)?;
+        self.trim_red_links()?;
+        Ok(self.conn)
+    }
+}
+
+pub const REDIRECT_TEXT: &str = "#REDIRECT [[";
+
+/// Link for a redirect to `redirect`, without any section and ignoring redirects out of the
+/// included namespaces
+pub fn get_redirect_link(redirect: &str, filter: &NamespaceFilter) -> Vec<WikiLink> {
+    let redirect = redirect.split('#').next().unwrap().trim();
+    if !filter.includes_link(redirect) {
+        return Vec::new();
+    }
+    let target = redirect.strip_prefix(':').unwrap_or(redirect).trim().to_string();
+    vec![WikiLink { text: target.clone(), target, section: None, offset: 0 }]
+}
+
+pub fn get_links_from_body(body: String, title: &String, filter: &NamespaceFilter, link_options: &LinkOptions) -> Result<(Vec<WikiLink>, bool), String> {
+    if body.len() > REDIRECT_TEXT.len() && body.is_char_boundary(REDIRECT_TEXT.len()) && &body[..REDIRECT_TEXT.len()] == REDIRECT_TEXT {
+        let end = body.find("]]");
+        if let Some(end) = end {
+            let redirect = body[REDIRECT_TEXT.len()..end].trim();
+            Ok((get_redirect_link(redirect, filter), true))
+        } else {
+            Err(format!("Getting redirect link from '{}' failed", title))
+        }
+    } else {
+        let references = wikitext::extract_links(&body, &filter.namespaces, link_options)
+            .into_iter()
+            .filter(|link| filter.includes_link(&link.target))
+            // A leading colon links to a page rather than e.g. adding a category
+            .map(|link| WikiLink {
+                target: link.target.strip_prefix(':').unwrap_or(&link.target).trim().to_string(),
+                ..link
+            })
+            .filter(|link| !link.target.is_empty())
+            .collect();
+
+        Ok((references, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(target: &str) -> WikiLink {
+        WikiLink { text: target.to_string(), target: target.to_string(), section: None, offset: 0 }
+    }
+
+    fn rows<T: rusqlite::types::FromSql>(conn: &Connection, query: &str) -> Vec<T> {
+        let mut stmt = conn.prepare(query).unwrap();
+        stmt.query_map([], |row| row.get(0)).unwrap().map(|row| row.unwrap()).collect()
+    }
+
+    #[test]
+    fn hash_collisions() {
+        let mut builder = Builder::new(":memory:", 2, 100, 100, false).unwrap();
+        // Alpha and Beta hash to the same id
+        builder.page_id = |title| if title == "Alpha" || title == "Beta" { 42 } else { page_id(title) };
+        builder.cache("Source".to_string(), vec![link("Alpha"), link("Beta"), link("Alpha")], false).unwrap();
+        builder.cache("Alpha".to_string(), vec![link("Beta")], false).unwrap();
+        builder.cache("Beta".to_string(), Vec::new(), false).unwrap();
+        let conn = builder.finish(false).unwrap();
+
+        let beta = salted_page_id("Beta", 1);
+        let collisions: Vec<(i64, String, String, i64)> = conn.prepare("SELECT * FROM hash_collisions").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))).unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(collisions, vec![(42, "Beta".to_string(), "Alpha".to_string(), beta)]);
+
+        assert_eq!(rows::<String>(&conn, "SELECT title FROM pages WHERE id = 42"), ["Alpha"]);
+        assert_eq!(rows::<String>(&conn, &format!("SELECT title FROM pages WHERE id = {}", beta)), ["Beta"]);
+
+        // Both of Source's links survive, each pointing at its own page, and the repeated link is dropped
+        let source = page_id("Source");
+        let mut links = rows::<i64>(&conn, &format!("SELECT destination_id FROM links WHERE source_id = {}", source));
+        links.sort();
+        let mut expected = vec![42, beta];
+        expected.sort();
+        assert_eq!(links, expected);
+        assert_eq!(rows::<i64>(&conn, "SELECT destination_id FROM links WHERE source_id = 42"), [beta]);
+    }
+
+    /// Source links to A -> B -> C, to C directly, to a D <-> E loop and to F, which redirects to a
+    /// missing page. Other links to B.
+    fn redirects(rewrite_links: bool) -> Connection {
+        let mut builder = Builder::new(":memory:", 10, 100, 100, false).unwrap();
+        builder.cache("Source".to_string(), vec![link("A"), link("C"), link("D"), link("F")], false).unwrap();
+        builder.cache("Other".to_string(), vec![link("B")], false).unwrap();
+        for (redirect, target) in [("A", "B"), ("B", "C"), ("D", "E"), ("E", "D"), ("F", "Missing")] {
+            builder.cache(redirect.to_string(), vec![link(target)], true).unwrap();
+        }
+        builder.cache("C".to_string(), Vec::new(), false).unwrap();
+        builder.finish(rewrite_links).unwrap()
+    }
+
+    /// Destination and redirect of each of `source`'s links
+    fn links_of(conn: &Connection, source: &str) -> Vec<(i64, Option<i64>)> {
+        let mut stmt = conn.prepare("SELECT destination_id, redirect_id FROM links WHERE source_id = ? ORDER BY destination_id").unwrap();
+        stmt.query_map([page_id(source)], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(|row| row.unwrap()).collect()
+    }
+
+    fn sorted(mut links: Vec<(i64, Option<i64>)>) -> Vec<(i64, Option<i64>)> {
+        links.sort();
+        links
+    }
+
+    #[test]
+    fn redirect_chains() {
+        let conn = redirects(false);
+
+        let mut resolved: Vec<(i64, i64)> = conn.prepare("SELECT source_id, target_id FROM redirects").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        resolved.sort();
+        let mut expected = vec![(page_id("A"), page_id("C")), (page_id("B"), page_id("C"))];
+        expected.sort();
+        // The loop and the redirect to a missing page are left out
+        assert_eq!(resolved, expected);
+
+        // Links are left alone
+        let direct = |title| (page_id(title), None);
+        assert_eq!(links_of(&conn, "Source"), sorted(vec![direct("A"), direct("C"), direct("D"), direct("F")]));
+        assert_eq!(links_of(&conn, "Other"), [direct("B")]);
+    }
+
+    #[test]
+    fn rewritten_redirects() {
+        let conn = redirects(true);
+        assert_eq!(rows::<i64>(&conn, "SELECT COUNT(*) FROM redirects"), [2]);
+
+        // The link through A duplicated the direct link to C so it is gone, while the loop and the
+        // redirect to a missing page are still linked to as they were
+        let direct = |title| (page_id(title), None);
+        assert_eq!(links_of(&conn, "Source"), sorted(vec![direct("C"), direct("D"), direct("F")]));
+        assert_eq!(links_of(&conn, "Other"), [(page_id("C"), Some(page_id("B")))]);
+        assert_eq!(rows::<i64>(&conn, &format!("SELECT COUNT(*) FROM links WHERE destination_id IN ({}, {})", page_id("A"), page_id("B"))), [0]);
+    }
+
+    #[test]
+    fn link_details() {
+        // Destination, anchor, section, position and offset
+        type Details = (i64, Option<String>, Option<String>, Option<u32>, Option<u32>);
+        let links = || vec![
+            WikiLink { text: "the country".to_string(), target: "England".to_string(), section: None, offset: 12 },
+            WikiLink { text: "the country".to_string(), target: "England".to_string(), section: None, offset: 40 },
+            WikiLink { text: "France".to_string(), target: "France".to_string(), section: Some("Transport".to_string()), offset: 250 },
+        ];
+        let details = |conn: &Connection| -> Vec<Details> {
+            conn.prepare("SELECT destination_id, anchor, section, position, offset FROM links ORDER BY position, anchor").unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))).unwrap()
+                .map(|row| row.unwrap())
+                .collect()
+        };
+
+        let mut builder = Builder::new(":memory:", 10, 100, 100, true).unwrap();
+        builder.cache("London".to_string(), links(), false).unwrap();
+        let conn = builder.finish(false).unwrap();
+        // The lead is stored as an empty section, and positions count the repeated link that was dropped
+        let mut expected = vec![
+            (page_id("England"), Some("the country".to_string()), Some(String::new()), Some(0), Some(12)),
+            (page_id("France"), Some("France".to_string()), Some("Transport".to_string()), Some(2), Some(250)),
+        ];
+        assert_eq!(details(&conn), expected);
+
+        // Otherwise only link text that isn't the title is kept
+        let mut builder = Builder::new(":memory:", 10, 100, 100, false).unwrap();
+        builder.cache("London".to_string(), links(), false).unwrap();
+        let conn = builder.finish(false).unwrap();
+        expected = vec![
+            (page_id("England"), Some("the country".to_string()), None, None, None),
+            (page_id("France"), None, None, None, None),
+        ];
+        let mut stored = details(&conn);
+        stored.sort();
+        expected.sort();
+        assert_eq!(stored, expected);
+    }
+
+    #[test]
+    fn duplicate_pages() {
+        let mut builder = Builder::new(":memory:", 10, 100, 100, false).unwrap();
+        builder.cache("London".to_string(), vec![link("England")], false).unwrap();
+        builder.cache("london".to_string(), vec![link("France")], false).unwrap();
+        let conn = builder.finish(false).unwrap();
+
+        assert_eq!(rows::<String>(&conn, "SELECT title FROM pages"), ["London"]);
+        assert_eq!(rows::<i64>(&conn, "SELECT destination_id FROM links"), [page_id("England")]);
+    }
+}
@@ -0,0 +1,243 @@
+//! Where the tools read and write their files, how big the database builder's batches are and how
+//! results are printed
+//!
+//! Each setting comes from (later ones winning) its default, the config file, a `WIKI4_*` environment
+//! variable and a command line flag. The config file is `--config <path>`, `$WIKI4_CONFIG` or
+//! `wiki4.conf` in the working directory if it exists, with one `name = value` per line and lines starting
+//! with `#` as comments:
+//! ```text
+//! database = /data/wiki/completed-table.db
+//! format = json
+//! ```
+
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use crate::args::take_option;
+use crate::dump;
+
+pub const CONFIG_FILE: &str = "wiki4.conf";
+
+/// Name in the config file, environment variable and flag of each setting
+const SETTINGS: [(&str, &str, &str); 9] = [
+    ("dump", "WIKI4_DUMP", "--dump"),
+    ("index", "WIKI4_INDEX", "--index"),
+    ("database", "WIKI4_DATABASE", "--database"),
+    ("snapshot", "WIKI4_SNAPSHOT", "--snapshot"),
+    ("not_found", "WIKI4_NOT_FOUND", "--not-found"),
+    ("batch_size", "WIKI4_BATCH_SIZE", "--batch-size"),
+    ("pages_per_write", "WIKI4_PAGES_PER_WRITE", "--pages-per-write"),
+    ("links_per_write", "WIKI4_LINKS_PER_WRITE", "--links-per-write"),
+    ("format", "WIKI4_FORMAT", "--format"),
+];
+
+/// How results are printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    /// One JSON document per result on stdout, with everything else on stderr
+    Json,
+}
+
+impl Format {
+    /// Prints a message that isn't part of the result - progress, notes and errors
+    pub fn note(self, message: impl Display) {
+        match self {
+            Format::Text => println!("{}", message),
+            Format::Json => eprintln!("{}", message),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// `.xml` or `.xml.bz2` dump the database is built from
+    pub dump: String,
+    /// Index of a multistream dump, found next to it by default
+    pub index: Option<String>,
+    pub database: String,
+    /// Graph snapshot written by `export`
+    pub snapshot: String,
+    /// Where `sample` records the searches that found no path
+    pub not_found: String,
+    /// Rows inserted per statement while building
+    pub batch_size: usize,
+    /// Pages cached before they are written to the database
+    pub pages_per_write: usize,
+    /// Links cached before they are written to the database
+    pub links_per_write: usize,
+    pub format: Format,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            dump: String::new(),
+            index: None,
+            database: "completed-table.db".to_string(),
+            snapshot: "completed-graph.csr".to_string(),
+            not_found: "not_found.txt".to_string(),
+            batch_size: 1000,
+            pages_per_write: 1_000_000,
+            links_per_write: 75_000_000,
+            format: Format::Text,
+        }
+    }
+}
+
+fn parse_count(name: &str, value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("{} needs a number above 0, not '{}'", name, value)),
+    }
+}
+
+impl Config {
+    /// Reads the config file, environment and the settings' flags (which are removed from `args`)
+    pub fn load(args: &mut Vec<String>) -> Result<Config, String> {
+        let mut config = Config::default();
+
+        let explicit = match take_option(args, "--config")? {
+            Some(path) => Some(path),
+            None => env::var("WIKI4_CONFIG").ok(),
+        };
+        match explicit {
+            Some(path) => config.read_file(&path)?,
+            None if Path::new(CONFIG_FILE).exists() => config.read_file(CONFIG_FILE)?,
+            None => {}
+        }
+
+        for (name, variable, _) in SETTINGS {
+            if let Ok(value) = env::var(variable) {
+                config.set(name, &value).map_err(|e| format!("{} ({})", e, variable))?;
+            }
+        }
+
+        for (name, _, flag) in SETTINGS {
+            if let Some(value) = take_option(args, flag)? {
+                config.set(name, &value).map_err(|e| format!("{} ({})", e, flag))?;
+            }
+        }
+
+        if config.dump.is_empty() {
+            config.dump = dump::default_dump_path();
+        }
+        if config.index.is_none() {
+            config.index = dump::default_index_path(&config.dump);
+        }
+
+        Ok(config)
+    }
+
+    /// Applies the settings in the config file at `path`
+    pub fn read_file(&mut self, path: &str) -> Result<(), String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("Can't read config file '{}': {}", path, e))?;
+
+        for (number, line) in contents.lines().enumerate() {
+            // Only whole lines are comments, so values such as paths can have `#` in them
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((name, value)) = line.split_once('=') else {
+                return Err(format!("{}:{}: expected 'name = value'", path, number + 1));
+            };
+            self.set(name.trim(), value.trim()).map_err(|e| format!("{}:{}: {}", path, number + 1, e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the setting called `name` (as in the config file) from text
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "dump" => self.dump = value.to_string(),
+            "index" => self.index = Some(value.to_string()),
+            "database" => self.database = value.to_string(),
+            "snapshot" => self.snapshot = value.to_string(),
+            "not_found" => self.not_found = value.to_string(),
+            "batch_size" => self.batch_size = parse_count(name, value)?,
+            "pages_per_write" => self.pages_per_write = parse_count(name, value)?,
+            "links_per_write" => self.links_per_write = parse_count(name, value)?,
+            "format" => self.format = match value {
+                "text" => Format::Text,
+                "json" => Format::Json,
+                _ => return Err(format!("format needs to be 'text' or 'json', not '{}'", value)),
+            },
+            _ => return Err(format!("Unknown setting '{}'", name)),
+        }
+        Ok(())
+    }
+
+    /// Where the database is built before it replaces `database`, so a failed build leaves the last
+    /// one in place
+    pub fn building_path(&self) -> String {
+        format!("{}.partial", self.database)
+    }
+}
+
+/// `s` as a JSON string literal
+pub fn json_string(s: &str) -> String {
+    let mut output = String::with_capacity(s.len() + 2);
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings() {
+        let mut config = Config::default();
+        config.set("database", "/data/wiki.db").unwrap();
+        config.set("batch_size", "500").unwrap();
+        config.set("format", "json").unwrap();
+        assert_eq!(config.database, "/data/wiki.db");
+        assert_eq!(config.building_path(), "/data/wiki.db.partial");
+        assert_eq!(config.batch_size, 500);
+        assert_eq!(config.format, Format::Json);
+
+        assert!(config.set("batch_size", "0").is_err());
+        assert!(config.set("format", "xml").is_err());
+        assert!(config.set("colour", "blue").is_err());
+    }
+
+    #[test]
+    fn config_file() {
+        let path = env::temp_dir().join(format!("wiki4-config-test-{}.conf", std::process::id()));
+        fs::write(&path, "# Where everything goes\n\n  database = /data/wiki#2/graph.db\n\t# format = json\nbatch_size=20\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut config = Config::default();
+        config.read_file(path).unwrap();
+        assert_eq!(config.database, "/data/wiki#2/graph.db");
+        assert_eq!(config.batch_size, 20);
+        assert_eq!(config.format, Format::Text);
+
+        fs::write(path, "database\n").unwrap();
+        assert_eq!(config.read_file(path).unwrap_err(), format!("{}:1: expected 'name = value'", path));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn json_strings() {
+        assert_eq!(json_string("Paris & Co"), "\"Paris & Co\"");
+        assert_eq!(json_string("say \"hi\"\\\n"), "\"say \\\"hi\\\"\\\\\\n\"");
+        assert_eq!(json_string("\u{1}"), "\"\\u0001\"");
+    }
+}
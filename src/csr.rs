@@ -0,0 +1,474 @@
+//! Compressed sparse row snapshot of the link graph in the database
+//!
+//! Pages are given dense `u32` indices (in order of page id) so each page's links are one contiguous
+//! slice of the edge array. Backlinks are stored the same way so bidirectional searches work too.
+//!
+//! File layout (little endian, every section padded to 8 bytes):
+//! ```text
+//! header         MAGIC, version: u32, hash_version: u32, build_id: u64, node_count: u64, edge_count: u64,
+//!                title_bytes: u64
+//! ids            [i64; node_count]         page id of each index (sorted)
+//! redirects      [u8; node_count]          1 if the page is a redirect
+//! out_offsets    [u64; node_count + 1]
+//! out_edges      [u32; edge_count]
+//! in_offsets     [u64; node_count + 1]
+//! in_edges       [u32; edge_count]
+//! title_offsets  [u64; node_count + 1]
+//! titles         [u8; title_bytes]         utf-8
+//! ```
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::mem::{size_of, size_of_val};
+use std::ops::Range;
+use std::path::Path;
+use memmap2::Mmap;
+use rusqlite::Connection;
+use crate::page_id::{self, HASH_VERSION};
+
+pub const MAGIC: &[u8; 8] = b"WIKICSR\0";
+pub const VERSION: u32 = 3;
+const HEADER_SIZE: usize = 8 + 4 + 4 + 8 + 8 + 8 + 8;
+
+fn padded(bytes: usize) -> usize {
+    bytes.next_multiple_of(8)
+}
+
+pub struct CsrGraph {
+    mmap: Mmap,
+    node_count: usize,
+    edge_count: usize,
+    ids: Range<usize>,
+    redirects: Range<usize>,
+    out_offsets: Range<usize>,
+    out_edges: Range<usize>,
+    in_offsets: Range<usize>,
+    in_edges: Range<usize>,
+    title_offsets: Range<usize>,
+    titles: Range<usize>,
+}
+
+impl CsrGraph {
+    /// Opens the snapshot at `path`, which has to have been exported from the database in `conn` as it
+    /// is now
+    pub fn open<P: AsRef<Path>>(path: P, conn: &Connection) -> Result<CsrGraph, Error> {
+        if cfg!(target_endian = "big") {
+            return Err(Error::new(ErrorKind::Unsupported, "graph snapshots can only be loaded on little endian machines"));
+        }
+
+        let file = File::open(path)?;
+        // SAFETY: `write_snapshot` never changes a snapshot in place - it writes a new file and renames it
+        // over the old one, so the mapped file keeps its contents even if it is replaced. Only something
+        // other than wiki4 writing to the file could change it under the mapping.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        if mmap.len() < HEADER_SIZE || &mmap[..8] != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "file is not a graph snapshot"));
+        }
+        let read_u64 = |at: usize| u64::from_le_bytes(mmap[at..at + 8].try_into().unwrap()) as usize;
+        let version = u32::from_le_bytes(mmap[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("graph snapshot is version {} but version {} is expected - re-export it", version, VERSION)
+            ));
+        }
+        let hash_version = u32::from_le_bytes(mmap[12..16].try_into().unwrap());
+        if hash_version != HASH_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("graph snapshot page ids use hash version {} but version {} is expected - rebuild it", hash_version, HASH_VERSION)
+            ));
+        }
+        let build_id = read_u64(16) as u64;
+        let database_build_id = page_id::build_id(conn).map_err(|e| Error::other(format!("failed to read database: {}", e)))?;
+        if build_id != database_build_id {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "graph snapshot was exported from a different build of the database - re-export it with wiki4 export"
+            ));
+        }
+        let node_count = read_u64(24);
+        let edge_count = read_u64(32);
+        let title_bytes = read_u64(40);
+
+        let mut position = HEADER_SIZE;
+        let mut section = |bytes: usize| {
+            let range = position..position + bytes;
+            position += padded(bytes);
+            range
+        };
+        let ids = section(node_count * size_of::<i64>());
+        let redirects = section(node_count);
+        let out_offsets = section((node_count + 1) * size_of::<u64>());
+        let out_edges = section(edge_count * size_of::<u32>());
+        let in_offsets = section((node_count + 1) * size_of::<u64>());
+        let in_edges = section(edge_count * size_of::<u32>());
+        let title_offsets = section((node_count + 1) * size_of::<u64>());
+        let titles = section(title_bytes);
+
+        if mmap.len() < titles.end {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "graph snapshot is truncated"));
+        }
+
+        Ok(CsrGraph {
+            mmap,
+            node_count,
+            edge_count,
+            ids,
+            redirects,
+            out_offsets,
+            out_edges,
+            in_offsets,
+            in_edges,
+            title_offsets,
+            titles,
+        })
+    }
+
+    fn slice<T: Copy>(&self, range: &Range<usize>) -> &[T] {
+        // SAFETY: mmaps are page aligned and every section starts on an 8 byte boundary, and only
+        // plain integer types are read
+        let (prefix, slice, _) = unsafe { self.mmap[range.clone()].align_to::<T>() };
+        debug_assert!(prefix.is_empty());
+        slice
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edge_count
+    }
+
+    /// Finds the dense index of a page id
+    pub fn index_of(&self, id: i64) -> Option<u32> {
+        self.slice::<i64>(&self.ids).binary_search(&id).ok().map(|i| i as u32)
+    }
+
+    pub fn id(&self, index: u32) -> i64 {
+        self.slice::<i64>(&self.ids)[index as usize]
+    }
+
+    pub fn is_redirect(&self, index: u32) -> bool {
+        self.mmap[self.redirects.start + index as usize] != 0
+    }
+
+    pub fn title(&self, index: u32) -> &str {
+        let offsets = self.slice::<u64>(&self.title_offsets);
+        let start = self.titles.start + offsets[index as usize] as usize;
+        let end = self.titles.start + offsets[index as usize + 1] as usize;
+        std::str::from_utf8(&self.mmap[start..end]).unwrap()
+    }
+
+    /// Indices of the pages `index` links to
+    pub fn links(&self, index: u32) -> &[u32] {
+        let offsets = self.slice::<u64>(&self.out_offsets);
+        &self.slice::<u32>(&self.out_edges)[offsets[index as usize] as usize..offsets[index as usize + 1] as usize]
+    }
+
+    /// Indices of the pages that link to `index`
+    pub fn backlinks(&self, index: u32) -> &[u32] {
+        let offsets = self.slice::<u64>(&self.in_offsets);
+        &self.slice::<u32>(&self.in_edges)[offsets[index as usize] as usize..offsets[index as usize + 1] as usize]
+    }
+
+    /// Page ids linked to from the page `id`
+    pub fn links_of_id(&self, id: i64) -> Vec<i64> {
+        match self.index_of(id) {
+            Some(index) => self.links(index).iter().map(|&i| self.id(i)).collect(),
+            None => Vec::new()
+        }
+    }
+
+    /// Page ids that link to the page `id`
+    pub fn backlinks_of_id(&self, id: i64) -> Vec<i64> {
+        match self.index_of(id) {
+            Some(index) => self.backlinks(index).iter().map(|&i| self.id(i)).collect(),
+            None => Vec::new()
+        }
+    }
+}
+
+/// Pages (in order of id) and their links in CSR form, read straight from the database
+pub struct LinkTable {
+    pub ids: Vec<i64>,
+    pub titles: Vec<String>,
+    pub redirects: Vec<bool>,
+    pub out_offsets: Vec<u64>,
+    pub out_edges: Vec<u32>,
+    /// Links skipped because their source or destination isn't a page
+    pub dangling: u64,
+    /// `build_id` of the database the links were read from
+    pub build_id: u64,
+}
+
+impl LinkTable {
+    pub fn read(conn: &Connection) -> rusqlite::Result<LinkTable> {
+        let build_id = page_id::build_id(conn)?;
+        let mut ids = Vec::new();
+        let mut titles = Vec::new();
+        let mut redirects = Vec::new();
+
+        let mut stmt = conn.prepare("SELECT id, title, is_redirect FROM pages ORDER BY id")?;
+        let mut rows = stmt.query(())?;
+        while let Some(row) = rows.next()? {
+            ids.push(row.get::<_, i64>(0)?);
+            titles.push(row.get::<_, String>(1)?);
+            redirects.push(row.get::<_, bool>(2)?);
+        }
+        drop(rows);
+        drop(stmt);
+
+        let mut out_offsets = Vec::with_capacity(ids.len() + 1);
+        out_offsets.push(0u64);
+        let mut out_edges: Vec<u32> = Vec::new();
+        let mut dangling: u64 = 0;
+
+        let mut stmt = conn.prepare("SELECT source_id, destination_id FROM links ORDER BY source_id")?;
+        let mut rows = stmt.query(())?;
+        let mut source_index = 0;
+        while let Some(row) = rows.next()? {
+            let source_id: i64 = row.get(0)?;
+            let destination_id: i64 = row.get(1)?;
+
+            // Links from pages that were never written (e.g. failed inserts) are dropped
+            let Ok(source) = ids[source_index..].binary_search(&source_id).map(|i| i + source_index) else {
+                dangling += 1;
+                continue;
+            };
+            while source_index < source {
+                out_offsets.push(out_edges.len() as u64);
+                source_index += 1;
+            }
+
+            // Links to pages that don't exist can never be part of a path
+            match ids.binary_search(&destination_id) {
+                Ok(destination) => out_edges.push(destination as u32),
+                Err(_) => dangling += 1
+            }
+        }
+        while out_offsets.len() < ids.len() + 1 {
+            out_offsets.push(out_edges.len() as u64);
+        }
+
+        Ok(LinkTable {
+            ids,
+            titles,
+            redirects,
+            out_offsets,
+            out_edges,
+            dangling,
+            build_id,
+        })
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Indices of the pages `index` links to
+    pub fn links(&self, index: u32) -> &[u32] {
+        &self.out_edges[self.out_offsets[index as usize] as usize..self.out_offsets[index as usize + 1] as usize]
+    }
+
+    pub fn write_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        write_snapshot(path, self.build_id, &self.ids, &self.redirects, &self.titles, &self.out_offsets, &self.out_edges)
+    }
+}
+
+/// Pages by dense index from the graph snapshot if there is one, otherwise read from the database
+pub enum IndexedGraph {
+    Snapshot(CsrGraph),
+    Table(LinkTable),
+}
+
+impl IndexedGraph {
+    /// Opens the snapshot at `snapshot_path`, or reads the links from `conn` if it doesn't exist or
+    /// can't be used with `conn`
+    pub fn load<P: AsRef<Path>>(conn: &Connection, snapshot_path: P) -> Result<IndexedGraph, String> {
+        if snapshot_path.as_ref().exists() {
+            println!("Loading graph snapshot '{}'", snapshot_path.as_ref().display());
+            match CsrGraph::open(snapshot_path, conn) {
+                Ok(graph) => return Ok(IndexedGraph::Snapshot(graph)),
+                Err(e) => println!("Not using graph snapshot: {}", e),
+            }
+        }
+
+        println!("Reading pages and links");
+        LinkTable::read(conn)
+            .map(IndexedGraph::Table)
+            .map_err(|e| format!("Failed to read pages and links: {:?}", e))
+    }
+
+    pub fn node_count(&self) -> usize {
+        match self {
+            IndexedGraph::Snapshot(graph) => graph.node_count(),
+            IndexedGraph::Table(table) => table.node_count(),
+        }
+    }
+
+    pub fn links(&self, index: u32) -> &[u32] {
+        match self {
+            IndexedGraph::Snapshot(graph) => graph.links(index),
+            IndexedGraph::Table(table) => table.links(index),
+        }
+    }
+
+    pub fn id(&self, index: u32) -> i64 {
+        match self {
+            IndexedGraph::Snapshot(graph) => graph.id(index),
+            IndexedGraph::Table(table) => table.ids[index as usize],
+        }
+    }
+
+    pub fn title(&self, index: u32) -> &str {
+        match self {
+            IndexedGraph::Snapshot(graph) => graph.title(index),
+            IndexedGraph::Table(table) => &table.titles[index as usize],
+        }
+    }
+
+    pub fn is_redirect(&self, index: u32) -> bool {
+        match self {
+            IndexedGraph::Snapshot(graph) => graph.is_redirect(index),
+            IndexedGraph::Table(table) => table.redirects[index as usize],
+        }
+    }
+}
+
+fn write_padding<W: Write>(writer: &mut W, bytes: usize) -> Result<(), Error> {
+    writer.write_all(&[0u8; 8][..padded(bytes) - bytes])
+}
+
+/// Writes a snapshot given pages sorted by id and the forward edges in CSR form - backlinks are
+/// derived here. The snapshot is written to `<path>.tmp` and then renamed to `path`, so searches that
+/// have the previous snapshot mapped aren't affected.
+pub fn write_snapshot<P: AsRef<Path>>(
+    path: P,
+    build_id: u64,
+    ids: &[i64],
+    redirects: &[bool],
+    titles: &[String],
+    out_offsets: &[u64],
+    out_edges: &[u32],
+) -> Result<(), Error> {
+    let node_count = ids.len();
+    let edge_count = out_edges.len();
+    assert_eq!(redirects.len(), node_count);
+    assert_eq!(titles.len(), node_count);
+    assert_eq!(out_offsets.len(), node_count + 1);
+
+    // Counting sort of the forward edges by destination
+    let mut in_offsets = vec![0u64; node_count + 1];
+    for &destination in out_edges {
+        in_offsets[destination as usize + 1] += 1;
+    }
+    for i in 0..node_count {
+        in_offsets[i + 1] += in_offsets[i];
+    }
+    let mut in_edges = vec![0u32; edge_count];
+    let mut next = in_offsets.clone();
+    for source in 0..node_count {
+        for &destination in &out_edges[out_offsets[source] as usize..out_offsets[source + 1] as usize] {
+            in_edges[next[destination as usize] as usize] = source as u32;
+            next[destination as usize] += 1;
+        }
+    }
+    drop(next);
+
+    let title_bytes: usize = titles.iter().map(|t| t.len()).sum();
+
+    let mut temp_path = path.as_ref().as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&HASH_VERSION.to_le_bytes())?;
+    writer.write_all(&build_id.to_le_bytes())?;
+    writer.write_all(&(node_count as u64).to_le_bytes())?;
+    writer.write_all(&(edge_count as u64).to_le_bytes())?;
+    writer.write_all(&(title_bytes as u64).to_le_bytes())?;
+
+    for id in ids {
+        writer.write_all(&id.to_le_bytes())?;
+    }
+    for &redirect in redirects {
+        writer.write_all(&[redirect as u8])?;
+    }
+    write_padding(&mut writer, node_count)?;
+    for offset in out_offsets {
+        writer.write_all(&offset.to_le_bytes())?;
+    }
+    for edge in out_edges {
+        writer.write_all(&edge.to_le_bytes())?;
+    }
+    write_padding(&mut writer, size_of_val(out_edges))?;
+    for offset in &in_offsets {
+        writer.write_all(&offset.to_le_bytes())?;
+    }
+    for edge in &in_edges {
+        writer.write_all(&edge.to_le_bytes())?;
+    }
+    write_padding(&mut writer, size_of_val(out_edges))?;
+    let mut title_offset = 0u64;
+    writer.write_all(&title_offset.to_le_bytes())?;
+    for title in titles {
+        title_offset += title.len() as u64;
+        writer.write_all(&title_offset.to_le_bytes())?;
+    }
+    for title in titles {
+        writer.write_all(title.as_bytes())?;
+    }
+    write_padding(&mut writer, title_bytes)?;
+
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use crate::builder::Builder;
+    use crate::wikitext::WikiLink;
+
+    fn link(target: &str) -> WikiLink {
+        WikiLink { text: target.to_string(), target: target.to_string(), section: None, offset: 0 }
+    }
+
+    #[test]
+    fn snapshot_matches_database() {
+        let mut builder = Builder::new(":memory:", 10, 100, 100, false).unwrap();
+        builder.cache("London".to_string(), vec![link("England"), link("Atlantis")], false).unwrap();
+        builder.cache("England".to_string(), vec![link("London")], false).unwrap();
+        let conn = builder.finish(false).unwrap();
+
+        let path = env::temp_dir().join(format!("wiki4-csr-test-{}.csr", std::process::id()));
+        let table = LinkTable::read(&conn).unwrap();
+        assert_eq!(table.dangling, 1);
+        table.write_snapshot(&path).unwrap();
+
+        let graph = CsrGraph::open(&path, &conn).unwrap();
+        assert_eq!((graph.node_count(), graph.edge_count()), (2, 2));
+        let london = graph.index_of(page_id::page_id("London")).unwrap();
+        assert_eq!(graph.title(london), "London");
+        assert_eq!(graph.links_of_id(page_id::page_id("London")), [page_id::page_id("England")]);
+        assert_eq!(graph.backlinks_of_id(page_id::page_id("London")), [page_id::page_id("England")]);
+
+        // Exporting again replaces the file rather than writing over the one that is mapped
+        table.write_snapshot(&path).unwrap();
+        assert_eq!(graph.title(london), "London");
+        assert!(!path.with_extension("csr.tmp").exists());
+
+        // Once the database is rebuilt the snapshot no longer describes it
+        page_id::record_build_id(&conn).unwrap();
+        let e = CsrGraph::open(&path, &conn).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(matches!(IndexedGraph::load(&conn, &path), Ok(IndexedGraph::Table(_))));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
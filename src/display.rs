@@ -0,0 +1,227 @@
+//! How paths are shown: one page per line, with the link clicked to reach each page and any redirect
+//! it went through when the database has them
+
+use rusqlite::{Connection, OptionalExtension};
+use crate::config::json_string;
+use crate::graph::{GraphError, WikiGraph};
+
+/// What the database knows about a link besides its ends
+#[derive(Default)]
+struct LinkDetails {
+    /// Text of the link, if it isn't the destination's title or the database was built with `--link-details`
+    anchor: Option<String>,
+    /// Heading the link is under (empty in the lead), with `--link-details`
+    section: Option<String>,
+    /// Redirect the link originally went to, if the build pointed it straight at the redirect's target
+    redirect: Option<i64>,
+}
+
+fn link_details(db: &Connection, source: i64, destination: i64) -> LinkDetails {
+    db.prepare_cached("SELECT anchor, section, redirect_id FROM links WHERE source_id = ? AND destination_id = ?").unwrap()
+        .query_row([source, destination], |row| Ok(LinkDetails {
+            anchor: row.get(0)?,
+            section: row.get(1)?,
+            redirect: row.get(2)?,
+        }))
+        .optional()
+        .unwrap()
+        .unwrap_or_default()
+}
+
+/// A page of a path as it is printed
+struct Step {
+    title: String,
+    /// The link clicked to reach the page
+    link: LinkDetails,
+    /// Redirect the link went through
+    via: Option<String>,
+    redirect: bool,
+}
+
+/// The pages of a path with the link clicked to reach each one. Redirects are a note on the page they
+/// led to rather than a step of their own - whether the search passed through the redirect page or
+/// followed a link `details` says was rewritten past it.
+fn steps(path: &[i64], graph: &mut dyn WikiGraph, details: Option<&Connection>) -> Result<Vec<Step>, GraphError> {
+    let mut steps = Vec::with_capacity(path.len());
+    let mut source: Option<i64> = None;
+    // First redirect passed through since `source`
+    let mut via: Option<(i64, String)> = None;
+
+    for (i, &page) in path.iter().enumerate() {
+        let (title, redirect) = graph.page(page)?.ok_or(GraphError::MissingPage(page))?;
+        let last = i == path.len() - 1;
+        if redirect && i > 0 && !last {
+            // The first redirect of a double redirect is the one that was linked to
+            via.get_or_insert((page, title));
+            continue;
+        }
+
+        let mut link = LinkDetails::default();
+        if let (Some(db), Some(source)) = (details, source) {
+            let destination = via.as_ref().map_or(page, |(id, _)| *id);
+            link = link_details(db, source, destination);
+            if via.is_none() {
+                via = match link.redirect {
+                    Some(id) => Some((id, graph.title(id)?)),
+                    None => None,
+                };
+            }
+        }
+
+        steps.push(Step { title, link, via: via.take().map(|(_, title)| title), redirect });
+        source = Some(page);
+    }
+
+    Ok(steps)
+}
+
+/// Title followed by the link clicked to reach it and the redirect it went through, if known
+fn fmt_title(step: &Step) -> String {
+    let click = match (&step.link.anchor, &step.link.section) {
+        (anchor, Some(section)) => {
+            let anchor = anchor.as_deref().unwrap_or(&step.title);
+            if section.is_empty() {
+                Some(format!("click '{}' in the introduction", anchor))
+            }
+            else {
+                Some(format!("click '{}' in section {}", anchor, section))
+            }
+        }
+        (Some(anchor), None) => Some(format!("click '{}'", anchor)),
+        (None, None) => None,
+    };
+    let notes: Vec<String> = [
+        click,
+        step.via.as_ref().map(|via| format!("via redirect '{}'", via)),
+    ].into_iter().flatten().collect();
+
+    if notes.is_empty() {
+        step.title.clone()
+    }
+    else {
+        format!("{} ({})", step.title, notes.join(", "))
+    }
+}
+
+/// Formats a path one page per line, each with the text of the link clicked to reach it where that
+/// isn't its title. `details` is the database to read link text from, or `None` for databases built
+/// before it was kept.
+pub fn fmt_path(path: &[i64], graph: &mut dyn WikiGraph, details: Option<&Connection>) -> Result<String, GraphError> {
+    let steps = steps(path, graph, details)?;
+    let mut output = String::new();
+
+    for (i, step) in steps.iter().enumerate() {
+        output += fmt_title(step).as_str();
+        if i < steps.len() - 1 {
+            output += if step.redirect { " =?=>\n" } else { " --->\n" };
+        }
+    }
+
+    Ok(output)
+}
+
+fn json_option(value: Option<&str>) -> String {
+    value.map_or("null".to_string(), json_string)
+}
+
+/// A path as a JSON object with its length and pages, each with the link clicked to reach it
+pub fn json_path(path: &[i64], graph: &mut dyn WikiGraph, details: Option<&Connection>) -> Result<String, GraphError> {
+    let steps = steps(path, graph, details)?;
+    let pages: Vec<String> = steps.iter()
+        .map(|step| format!(
+            "{{\"title\":{},\"link_text\":{},\"section\":{},\"via_redirect\":{}}}",
+            json_string(&step.title),
+            json_option(step.link.anchor.as_deref()),
+            json_option(step.link.section.as_deref()),
+            json_option(step.via.as_deref())
+        ))
+        .collect();
+    // Redirects passed through aren't steps, so aren't counted as links
+    Ok(format!("{{\"links\":{},\"pages\":[{}]}}", steps.len() - 1, pages.join(",")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Builder;
+    use crate::graph::SqliteGraph;
+    use crate::page_id::page_id;
+    use crate::wikitext::WikiLink;
+
+    /// Bedford links to the redirect Old London in its introduction, and London links to England under
+    /// Geography
+    fn database(link_details: bool, rewrite_redirects: bool) -> Connection {
+        let link = |text: &str, target: &str, section: Option<&str>| WikiLink {
+            text: text.to_string(),
+            target: target.to_string(),
+            section: section.map(str::to_string),
+            offset: 0,
+        };
+        let mut builder = Builder::new(":memory:", 10, 100, 100, link_details).unwrap();
+        builder.cache("Bedford".to_string(), vec![link("the old city", "Old London", None)], false).unwrap();
+        builder.cache("Old London".to_string(), vec![link("London", "London", None)], true).unwrap();
+        builder.cache("London".to_string(), vec![link("England", "England", Some("Geography"))], false).unwrap();
+        builder.cache("England".to_string(), Vec::new(), false).unwrap();
+        builder.finish(rewrite_redirects).unwrap()
+    }
+
+    fn ids(titles: &[&str]) -> Vec<i64> {
+        titles.iter().map(|title| page_id(title)).collect()
+    }
+
+    #[test]
+    fn link_details() {
+        let conn = database(true, false);
+        let mut graph = SqliteGraph::new(&conn).unwrap();
+        let path = ids(&["Bedford", "Old London", "London", "England"]);
+
+        assert_eq!(
+            fmt_path(&path, &mut graph, Some(&conn)).unwrap(),
+            "Bedford --->\n\
+             London (click 'the old city' in the introduction, via redirect 'Old London') --->\n\
+             England (click 'England' in section Geography)"
+        );
+        // Without the database only the redirect is known
+        assert_eq!(
+            fmt_path(&path, &mut graph, None).unwrap(),
+            "Bedford --->\nLondon (via redirect 'Old London') --->\nEngland"
+        );
+    }
+
+    #[test]
+    fn rewritten_redirects() {
+        // The link was pointed past the redirect, which the database still remembers
+        let conn = database(true, true);
+        let mut graph = SqliteGraph::new(&conn).unwrap();
+        assert_eq!(
+            fmt_path(&ids(&["Bedford", "London", "England"]), &mut graph, Some(&conn)).unwrap(),
+            "Bedford --->\n\
+             London (click 'the old city' in the introduction, via redirect 'Old London') --->\n\
+             England (click 'England' in section Geography)"
+        );
+    }
+
+    #[test]
+    fn link_text() {
+        // Without --link-details only text that differs from the title is kept, with no section
+        let conn = database(false, false);
+        let mut graph = SqliteGraph::new(&conn).unwrap();
+        assert_eq!(
+            fmt_path(&ids(&["Bedford", "Old London", "London", "England"]), &mut graph, Some(&conn)).unwrap(),
+            "Bedford --->\nLondon (click 'the old city', via redirect 'Old London') --->\nEngland"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let conn = database(true, false);
+        let mut graph = SqliteGraph::new(&conn).unwrap();
+        assert_eq!(
+            json_path(&ids(&["Bedford", "Old London", "London", "England"]), &mut graph, Some(&conn)).unwrap(),
+            "{\"links\":2,\"pages\":[\
+             {\"title\":\"Bedford\",\"link_text\":null,\"section\":null,\"via_redirect\":null},\
+             {\"title\":\"London\",\"link_text\":\"the old city\",\"section\":\"\",\"via_redirect\":\"Old London\"},\
+             {\"title\":\"England\",\"link_text\":\"England\",\"section\":\"Geography\",\"via_redirect\":null}]}"
+        );
+    }
+}
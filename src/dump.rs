@@ -0,0 +1,410 @@
+//! Streaming reader over the `<page>` elements of a MediaWiki XML dump
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use bzip2::read::{BzDecoder, MultiBzDecoder};
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+use crate::namespace::Namespaces;
+
+pub use xml::reader::Error;
+
+pub const DEFAULT_DUMP_PATH: &str = "enwiki-20231101-pages-articles-multistream.xml";
+
+/// The uncompressed default dump if it is there, otherwise the published `.bz2` file
+pub fn default_dump_path() -> String {
+    if Path::new(DEFAULT_DUMP_PATH).exists() {
+        DEFAULT_DUMP_PATH.to_string()
+    }
+    else {
+        format!("{}.bz2", DEFAULT_DUMP_PATH)
+    }
+}
+
+/// Opens a dump, decompressing it on the fly if it is a `.bz2` file. Multistream files (many bzip2
+/// streams one after another) are read all the way through.
+pub fn open_dump<P: AsRef<Path>>(path: P) -> std::io::Result<Box<dyn BufRead>> {
+    let compressed = path.as_ref().extension().is_some_and(|e| e == "bz2");
+    let file = File::open(path)?;
+
+    if compressed {
+        Ok(Box::new(BufReader::new(MultiBzDecoder::new(BufReader::new(file)))))
+    }
+    else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Index that ships with a multistream dump, if `dump_path` is one and the index is next to it
+pub fn default_index_path(dump_path: &str) -> Option<String> {
+    let index_path = dump_path.strip_suffix("multistream.xml.bz2")?.to_string() + "multistream-index.txt.bz2";
+    Path::new(&index_path).exists().then_some(index_path)
+}
+
+/// Offsets of the bzip2 streams in a multistream dump from its index of `offset:page_id:title` lines.
+/// The header (`<siteinfo>`) is the stream before the first offset.
+pub fn read_index<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<u64>> {
+    let mut offsets: Vec<u64> = Vec::new();
+
+    for line in open_dump(path)?.lines() {
+        let line = line?;
+        let Some(offset) = line.split(':').next().and_then(|o| o.parse().ok()) else {
+            continue;
+        };
+        if offsets.last() != Some(&offset) {
+            offsets.push(offset);
+        }
+    }
+
+    offsets.sort_unstable();
+    offsets.dedup();
+    Ok(offsets)
+}
+
+/// Decompresses the single bzip2 stream between `start` and `end` of a multistream dump
+pub fn open_stream<P: AsRef<Path>>(path: P, start: u64, end: u64) -> std::io::Result<impl BufRead> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    Ok(BufReader::new(BzDecoder::new(BufReader::new(file.take(end - start)))))
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DumpPage {
+    pub title: String,
+    pub namespace: i64,
+    pub id: u64,
+    /// Title from the page's `<redirect>` element, if it is a redirect
+    pub redirect: Option<String>,
+    pub timestamp: String,
+    pub text: String,
+}
+
+/// Yields each page in the dump with XML entities decoded
+pub struct DumpReader<R: Read> {
+    events: EventReader<R>,
+    namespaces: Namespaces,
+    /// Reading a run of `<page>` elements cut out of a dump rather than a whole one
+    fragment: bool,
+    finished: bool,
+    page: Option<DumpPage>,
+    /// Elements from the current `<page>` down
+    path: Vec<String>,
+    /// Text of the innermost element
+    characters: String,
+}
+
+impl<R: Read> DumpReader<R> {
+    /// Starts reading a dump, reading the namespaces from its `<siteinfo>` header straight away
+    pub fn new(source: R) -> Result<DumpReader<R>, Error> {
+        let mut reader = Self::with_namespaces(source, Namespaces::new(), false);
+        reader.read_header()?;
+
+        if reader.namespaces.is_empty() {
+            reader.namespaces = Namespaces::default();
+        }
+
+        Ok(reader)
+    }
+
+    /// Reads the pages in one stream of a multistream dump - there is no header, so the namespaces
+    /// come from the dump's first stream
+    pub fn fragment(source: R, namespaces: Namespaces) -> DumpReader<R> {
+        Self::with_namespaces(source, namespaces, true)
+    }
+
+    fn with_namespaces(source: R, namespaces: Namespaces, fragment: bool) -> DumpReader<R> {
+        let events = ParserConfig::new()
+            .trim_whitespace(false)
+            .whitespace_to_characters(true)
+            .cdata_to_characters(true)
+            .coalesce_characters(true)
+            .allow_multiple_root_elements(true)
+            .create_reader(source);
+
+        DumpReader {
+            events,
+            namespaces,
+            fragment,
+            finished: false,
+            page: None,
+            path: Vec::new(),
+            characters: String::new(),
+        }
+    }
+
+    /// Namespaces declared by the dump
+    pub fn namespaces(&self) -> &Namespaces {
+        &self.namespaces
+    }
+
+    fn read_header(&mut self) -> Result<(), Error> {
+        let mut namespace_key: Option<i64> = None;
+
+        loop {
+            match self.events.next()? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    match name.local_name.as_str() {
+                        "namespace" => {
+                            namespace_key = attributes.into_iter()
+                                .find(|a| a.name.local_name == "key")
+                                .and_then(|a| a.value.parse().ok());
+                            self.characters.clear();
+                        }
+                        "page" => {
+                            // No <siteinfo> - carry on from this page
+                            self.page = Some(DumpPage::default());
+                            self.path.push(name.local_name);
+                            return Ok(());
+                        }
+                        _ => {}
+                    }
+                }
+                XmlEvent::Characters(text) => {
+                    self.characters += text.as_str();
+                }
+                XmlEvent::EndElement { name } => {
+                    match name.local_name.as_str() {
+                        "namespace" => {
+                            if let Some(key) = namespace_key.take() {
+                                self.namespaces.add(key, &self.characters);
+                            }
+                        }
+                        "siteinfo" => {
+                            self.characters.clear();
+                            return Ok(());
+                        }
+                        _ => {}
+                    }
+                    self.characters.clear();
+                }
+                XmlEvent::EndDocument => {
+                    self.finished = true;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for DumpReader<R> {
+    type Item = Result<DumpPage, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        loop {
+            let event = match self.events.next() {
+                Ok(event) => event,
+                Err(e) => {
+                    self.finished = true;
+                    // Fragments end without closing the dump's root element (or close it without
+                    // opening it), which is fine between pages
+                    if self.fragment && self.page.is_none() {
+                        return None;
+                    }
+                    return Some(Err(e));
+                }
+            };
+
+            match event {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    if self.page.is_none() {
+                        if name.local_name == "page" {
+                            self.page = Some(DumpPage::default());
+                        }
+                        else {
+                            continue;
+                        }
+                    }
+
+                    if name.local_name == "redirect" && self.path.len() == 1 {
+                        self.page.as_mut().unwrap().redirect = attributes.into_iter()
+                            .find(|a| a.name.local_name == "title")
+                            .map(|a| a.value);
+                    }
+
+                    self.path.push(name.local_name);
+                    self.characters.clear();
+                }
+                XmlEvent::Characters(text) if self.page.is_some() => {
+                    self.characters += text.as_str();
+                }
+                XmlEvent::EndElement { .. } => {
+                    let Some(current) = self.page.as_mut() else { continue; };
+
+                    let path: Vec<&str> = self.path.iter().map(|s| s.as_str()).collect();
+                    match path.as_slice() {
+                        ["page"] => {
+                            self.path.clear();
+                            self.characters.clear();
+                            return self.page.take().map(Ok);
+                        }
+                        ["page", "title"] => current.title = std::mem::take(&mut self.characters),
+                        ["page", "ns"] => current.namespace = self.characters.trim().parse().unwrap_or(0),
+                        ["page", "id"] => current.id = self.characters.trim().parse().unwrap_or(0),
+                        ["page", "revision", "timestamp"] => current.timestamp = std::mem::take(&mut self.characters),
+                        ["page", "revision", "text"] => current.text = std::mem::take(&mut self.characters),
+                        _ => {}
+                    }
+
+                    self.path.pop();
+                    self.characters.clear();
+                }
+                XmlEvent::EndDocument => {
+                    self.finished = true;
+                    return None;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use bzip2::write::BzEncoder;
+    use bzip2::Compression;
+
+    const DUMP: &str = r#"<mediawiki xmlns="http://www.mediawiki.org/xml/export-0.10/">
+  <siteinfo>
+    <sitename>Wikipedia</sitename>
+    <namespaces>
+      <namespace key="0" case="first-letter" />
+      <namespace key="14" case="first-letter">Category</namespace>
+      <namespace key="100" case="first-letter">Portal</namespace>
+    </namespaces>
+  </siteinfo>
+  <page>
+    <title>AT&amp;T &lt;Corp&gt;</title>
+    <ns>0</ns>
+    <id>12</id>
+    <revision>
+      <id>999</id>
+      <timestamp>2023-11-01T00:00:00Z</timestamp>
+      <contributor>
+        <username>Someone</username>
+        <id>555</id>
+      </contributor>
+      <text bytes="40" xml:space="preserve">[[Bell &amp; Co|&quot;Bell&quot;]] &#233;t&#xE9;</text>
+    </revision>
+  </page>
+  <page>
+    <title>Ma Bell</title>
+    <ns>0</ns>
+    <id>13</id>
+    <redirect title="AT&amp;T &lt;Corp&gt;" />
+    <revision>
+      <id>1000</id>
+      <text>#REDIRECT [[AT&amp;T &lt;Corp&gt;]]</text>
+    </revision>
+  </page>
+  <page>
+    <title>Portal:Trains</title>
+    <ns>100</ns>
+    <id>14</id>
+    <revision>
+      <text />
+    </revision>
+  </page>
+</mediawiki>"#;
+
+    fn read(dump: &str) -> Vec<DumpPage> {
+        DumpReader::new(dump.as_bytes()).unwrap().map(|page| page.unwrap()).collect()
+    }
+
+    #[test]
+    fn pages() {
+        let pages = read(DUMP);
+        assert_eq!(pages.len(), 3);
+
+        assert_eq!(pages[0].title, "AT&T <Corp>");
+        assert_eq!(pages[0].namespace, 0);
+        // The revision's and contributor's ids don't replace the page's
+        assert_eq!(pages[0].id, 12);
+        assert_eq!(pages[0].timestamp, "2023-11-01T00:00:00Z");
+        assert_eq!(pages[0].text, "[[Bell & Co|\"Bell\"]] été");
+        assert_eq!(pages[0].redirect, None);
+
+        assert_eq!(pages[1].id, 13);
+        assert_eq!(pages[1].redirect.as_deref(), Some("AT&T <Corp>"));
+
+        assert_eq!(pages[2].namespace, 100);
+        assert_eq!(pages[2].text, "");
+    }
+
+    #[test]
+    fn namespaces() {
+        let reader = DumpReader::new(DUMP.as_bytes()).unwrap();
+        assert_eq!(reader.namespaces().classify("Portal:Trains"), 100);
+        assert_eq!(reader.namespaces().name(14), Some("Category"));
+        // Only what the dump declares, apart from the built in aliases
+        assert_eq!(reader.namespaces().name(10), None);
+    }
+
+    #[test]
+    fn fragment() {
+        let start = DUMP.find("  <page>").unwrap();
+        let end = DUMP.find("</mediawiki>").unwrap();
+        let namespaces = DumpReader::new(DUMP.as_bytes()).unwrap().namespaces().clone();
+        let pages: Vec<DumpPage> = DumpReader::fragment(&DUMP.as_bytes()[start..end], namespaces)
+            .map(|page| page.unwrap())
+            .collect();
+        assert_eq!(pages.iter().map(|page| page.id).collect::<Vec<u64>>(), [12, 13, 14]);
+    }
+
+    fn compress(text: &str) -> Vec<u8> {
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn multistream() {
+        let directory = env::temp_dir().join(format!("wiki4-dump-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let dump_path = directory.join("test-multistream.xml.bz2");
+        let index_path = directory.join("test-multistream-index.txt.bz2");
+
+        // Header, then two streams of pages
+        let first = DUMP.find("  <page>").unwrap();
+        let second = DUMP.find("  <page>\n    <title>Portal").unwrap();
+        let end = DUMP.find("</mediawiki>").unwrap();
+        let streams = [&DUMP[..first], &DUMP[first..second], &DUMP[second..end], &DUMP[end..]].map(compress);
+        let offsets: Vec<u64> = streams.iter()
+            .scan(0, |offset, stream| {
+                let start = *offset;
+                *offset += stream.len() as u64;
+                Some(start)
+            })
+            .collect();
+        fs::write(&dump_path, streams.concat()).unwrap();
+        let index = format!("{0}:12:AT&T <Corp>\n{0}:13:Ma Bell\n{1}:14:Portal:Trains\n", offsets[1], offsets[2]);
+        fs::write(&index_path, compress(&index)).unwrap();
+
+        let dump_path = dump_path.to_str().unwrap();
+        assert_eq!(default_index_path(dump_path).as_deref(), index_path.to_str());
+        let index = read_index(&index_path).unwrap();
+        assert_eq!(index, [offsets[1], offsets[2]]);
+
+        // The whole file reads as one dump
+        let pages: Vec<DumpPage> = DumpReader::new(open_dump(dump_path).unwrap()).unwrap().map(|page| page.unwrap()).collect();
+        assert_eq!(pages.len(), 3);
+
+        // And each stream on its own
+        let header = DumpReader::new(open_stream(dump_path, 0, index[0]).unwrap()).unwrap();
+        let stream = open_stream(dump_path, index[1], offsets[3]).unwrap();
+        let pages: Vec<DumpPage> = DumpReader::fragment(stream, header.namespaces().clone()).map(|page| page.unwrap()).collect();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].title, "Portal:Trains");
+        assert_eq!(pages[0].namespace, 100);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
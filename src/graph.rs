@@ -0,0 +1,294 @@
+//! The link graph as searches see it: pages by id, the pages each links to and from, and their titles
+//!
+//! `SqliteGraph` reads the database as it goes, `CsrGraph` reads the snapshot written by `wiki4 export`
+//! and `MemoryGraph` holds everything in memory, built up page by page or read from a
+//! database in one go.
+
+use std::collections::HashMap;
+use std::fmt;
+use rusqlite::{Connection, OptionalExtension, Statement};
+use crate::csr::CsrGraph;
+use crate::page_id::{self, page_id, salted_page_id, title_key};
+use crate::title;
+
+/// Why a graph couldn't answer
+#[derive(Debug)]
+pub enum GraphError {
+    Database(rusqlite::Error),
+    /// The database was built before the builder indexed links by destination, so backlinks can't be read
+    NoBacklinksIndex,
+    /// A page was linked to or asked for by id that the graph doesn't have
+    MissingPage(i64),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::Database(e) => write!(f, "Failed to read database: {}", e),
+            GraphError::NoBacklinksIndex => write!(f, "Database has no backlinks index - it was built by an older version and needs rebuilding with wiki4 build"),
+            GraphError::MissingPage(id) => write!(
+                f,
+                "There is no page with id {} - the database or graph snapshot is inconsistent, rebuild it with wiki4 build",
+                id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+impl From<rusqlite::Error> for GraphError {
+    fn from(e: rusqlite::Error) -> GraphError {
+        GraphError::Database(e)
+    }
+}
+
+/// Lets the tools, whose errors are messages, use `?` on graph errors
+impl From<GraphError> for String {
+    fn from(e: GraphError) -> String {
+        e.to_string()
+    }
+}
+
+pub trait WikiGraph {
+    /// Ids of the pages `id` links to
+    fn links(&mut self, id: i64) -> Result<Vec<i64>, GraphError>;
+
+    /// Ids of the pages that link to `id`
+    fn backlinks(&mut self, id: i64) -> Result<Vec<i64>, GraphError>;
+
+    /// Title of the page `id` and whether it is a redirect, if there is such a page
+    fn page(&mut self, id: i64) -> Result<Option<(String, bool)>, GraphError>;
+
+    /// Id of the page titled `title` (compared the way `page_id::title_key` does), if there is one
+    fn find(&mut self, title: &str) -> Result<Option<i64>, GraphError>;
+
+    /// Title of the page `id`, which is an error if there is no such page
+    fn title(&mut self, id: i64) -> Result<String, GraphError> {
+        self.page(id)?.map(|(title, _)| title).ok_or(GraphError::MissingPage(id))
+    }
+
+    /// Whether the page `id` is a redirect, which a missing page isn't
+    fn is_redirect(&mut self, id: i64) -> Result<bool, GraphError> {
+        Ok(self.page(id)?.is_some_and(|(_, is_redirect)| is_redirect))
+    }
+}
+
+/// Id of the page titled `title` given the title of the page with each id. Ids taken by a different
+/// title were salted in order when the graph was built, so they are tried in the same order.
+fn find_hashed(title: &str, mut title_of: impl FnMut(i64) -> Option<String>) -> Option<i64> {
+    let key = title_key(title);
+    let mut id = page_id(title);
+    let mut salt = 0;
+
+    while let Some(found) = title_of(id) {
+        if title_key(&found) == key {
+            return Some(id);
+        }
+        salt += 1;
+        id = salted_page_id(title, salt);
+    }
+
+    None
+}
+
+/// Whether `links` is indexed by destination, which backlinks are read with. The builder creates the
+/// index, so only databases from before it did are missing it.
+pub fn has_backlinks_index(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = 'links_destination')",
+        [],
+        |row| row.get(0)
+    )
+}
+
+/// Queries the database for each page as it is needed
+pub struct SqliteGraph<'a> {
+    conn: &'a Connection,
+    links: Statement<'a>,
+    backlinks: Statement<'a>,
+    page: Statement<'a>,
+    is_redirect: Statement<'a>,
+}
+
+impl<'a> SqliteGraph<'a> {
+    /// Prepares the queries on `conn`, which has to have been built with the backlinks index - without
+    /// it every backlink lookup would read the whole of `links`
+    pub fn new(conn: &'a Connection) -> Result<SqliteGraph<'a>, GraphError> {
+        if !has_backlinks_index(conn)? {
+            return Err(GraphError::NoBacklinksIndex);
+        }
+
+        Ok(SqliteGraph {
+            conn,
+            links: conn.prepare("SELECT destination_id FROM links WHERE source_id = ?")?,
+            backlinks: conn.prepare("SELECT source_id FROM links WHERE destination_id = ?")?,
+            page: conn.prepare("SELECT title, is_redirect FROM pages WHERE id = ?")?,
+            is_redirect: conn.prepare("SELECT is_redirect FROM pages WHERE id = ?")?,
+        })
+    }
+}
+
+fn query_ids(stmt: &mut Statement, id: i64) -> rusqlite::Result<Vec<i64>> {
+    stmt.query_map([id], |row| row.get(0))?.collect()
+}
+
+impl WikiGraph for SqliteGraph<'_> {
+    fn links(&mut self, id: i64) -> Result<Vec<i64>, GraphError> {
+        Ok(query_ids(&mut self.links, id)?)
+    }
+
+    fn backlinks(&mut self, id: i64) -> Result<Vec<i64>, GraphError> {
+        Ok(query_ids(&mut self.backlinks, id)?)
+    }
+
+    fn page(&mut self, id: i64) -> Result<Option<(String, bool)>, GraphError> {
+        Ok(self.page.query_row([id], |row| Ok((row.get(0)?, row.get(1)?))).optional()?)
+    }
+
+    fn find(&mut self, title: &str) -> Result<Option<i64>, GraphError> {
+        let id = page_id::resolve_title(self.conn, title)?;
+        Ok(self.page(id)?.map(|_| id))
+    }
+
+    fn is_redirect(&mut self, id: i64) -> Result<bool, GraphError> {
+        Ok(self.is_redirect.query_row([id], |row| row.get(0)).optional()?.unwrap_or(false))
+    }
+}
+
+impl WikiGraph for CsrGraph {
+    fn links(&mut self, id: i64) -> Result<Vec<i64>, GraphError> {
+        Ok(self.links_of_id(id))
+    }
+
+    fn backlinks(&mut self, id: i64) -> Result<Vec<i64>, GraphError> {
+        Ok(self.backlinks_of_id(id))
+    }
+
+    fn page(&mut self, id: i64) -> Result<Option<(String, bool)>, GraphError> {
+        Ok(self.index_of(id).map(|index| (CsrGraph::title(self, index).to_string(), CsrGraph::is_redirect(self, index))))
+    }
+
+    fn find(&mut self, title: &str) -> Result<Option<i64>, GraphError> {
+        Ok(find_hashed(title, |id| self.index_of(id).map(|index| CsrGraph::title(self, index).to_string())))
+    }
+
+    fn is_redirect(&mut self, id: i64) -> Result<bool, GraphError> {
+        Ok(self.index_of(id).is_some_and(|index| CsrGraph::is_redirect(self, index)))
+    }
+}
+
+/// Every page and link held in memory
+#[derive(Default)]
+pub struct MemoryGraph {
+    /// Title and whether the page is a redirect
+    pages: HashMap<i64, (String, bool)>,
+    links: HashMap<i64, Vec<i64>>,
+    backlinks: HashMap<i64, Vec<i64>>,
+}
+
+impl MemoryGraph {
+    pub fn new() -> MemoryGraph {
+        MemoryGraph::default()
+    }
+
+    /// Reads every page and link in `conn`
+    pub fn read(conn: &Connection) -> rusqlite::Result<MemoryGraph> {
+        let mut graph = MemoryGraph::new();
+
+        let mut stmt = conn.prepare("SELECT id, title, is_redirect FROM pages")?;
+        let pages = stmt.query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?;
+        for page in pages {
+            let (id, page) = page?;
+            graph.pages.insert(id, page);
+        }
+
+        let mut stmt = conn.prepare("SELECT source_id, destination_id FROM links")?;
+        let links = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for link in links {
+            let (source_id, destination_id) = link?;
+            graph.add_link(source_id, destination_id);
+        }
+
+        Ok(graph)
+    }
+
+    /// Adds the page titled `title` (normalised first), returning its id. Adding a title that is
+    /// already there returns the existing page's id.
+    pub fn add_page(&mut self, title: &str, is_redirect: bool) -> i64 {
+        let title = title::normalize(title);
+        if let Some(id) = find_hashed(&title, |id| self.pages.get(&id).map(|(title, _)| title.clone())) {
+            return id;
+        }
+
+        let mut id = page_id(&title);
+        let mut salt = 0;
+        while self.pages.contains_key(&id) {
+            salt += 1;
+            id = salted_page_id(&title, salt);
+        }
+        self.pages.insert(id, (title, is_redirect));
+        id
+    }
+
+    pub fn add_link(&mut self, source_id: i64, destination_id: i64) {
+        self.links.entry(source_id).or_default().push(destination_id);
+        self.backlinks.entry(destination_id).or_default().push(source_id);
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl WikiGraph for MemoryGraph {
+    fn links(&mut self, id: i64) -> Result<Vec<i64>, GraphError> {
+        Ok(self.links.get(&id).cloned().unwrap_or_default())
+    }
+
+    fn backlinks(&mut self, id: i64) -> Result<Vec<i64>, GraphError> {
+        Ok(self.backlinks.get(&id).cloned().unwrap_or_default())
+    }
+
+    fn page(&mut self, id: i64) -> Result<Option<(String, bool)>, GraphError> {
+        Ok(self.pages.get(&id).cloned())
+    }
+
+    fn find(&mut self, title: &str) -> Result<Option<i64>, GraphError> {
+        Ok(find_hashed(title, |id| self.pages.get(&id).map(|(title, _)| title.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn salted_ids() {
+        // Beta's id was taken by Alpha, so it was stored under its first salted id
+        let pages = HashMap::from([
+            (page_id("Beta"), "Alpha".to_string()),
+            (salted_page_id("Beta", 1), "Beta".to_string()),
+        ]);
+        let title_of = |id| pages.get(&id).cloned();
+        assert_eq!(find_hashed("beta", title_of), Some(salted_page_id("Beta", 1)));
+        assert_eq!(find_hashed("Gamma", title_of), None);
+    }
+
+    #[test]
+    fn memory_graph() {
+        let mut graph = MemoryGraph::new();
+        let london = graph.add_page("London", false);
+        let england = graph.add_page("England", false);
+        graph.add_link(london, england);
+
+        assert_eq!(graph.add_page("london", false), london);
+        assert_eq!(graph.find("LONDON").unwrap(), Some(london));
+        assert_eq!(graph.find("Paris").unwrap(), None);
+        assert_eq!(graph.links(london).unwrap(), vec![england]);
+        assert_eq!(graph.backlinks(england).unwrap(), vec![london]);
+        assert_eq!(graph.title(england).unwrap(), "England");
+        assert!(matches!(graph.title(0), Err(GraphError::MissingPage(0))));
+        assert_eq!(graph.page_count(), 2);
+    }
+}
@@ -0,0 +1,13 @@
+pub mod args;
+pub mod builder;
+pub mod config;
+pub mod csr;
+pub mod display;
+pub mod dump;
+pub mod graph;
+pub mod lookup;
+pub mod namespace;
+pub mod page_id;
+pub mod search;
+pub mod title;
+pub mod wikitext;
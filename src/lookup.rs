@@ -0,0 +1,246 @@
+//! Turns a title typed by a person into a page, following redirects, and suggests near misses when no
+//! page has that title
+//!
+//! Suggestions come from a trigram full text index over `pages.title` (`page_titles`), created the
+//! first time it is needed. Short titles share few trigrams with their typos, so when the index finds
+//! nothing close the titles of about the same length are checked one by one instead.
+
+use std::collections::HashMap;
+use rusqlite::{Connection, OptionalExtension};
+use crate::page_id;
+use crate::title;
+
+/// Candidates fetched from the index for each kind of match before ranking
+const CANDIDATES: usize = 200;
+
+/// Most edits a title found by checking every title of about the same length can be from the query
+const SCAN_DISTANCE: usize = 2;
+
+pub struct FoundPage {
+    pub id: i64,
+    pub title: String,
+    /// Title of the redirect that was followed to reach the page
+    pub redirect: Option<String>,
+}
+
+pub struct Suggestion {
+    pub title: String,
+    /// Page the suggestion redirects to
+    pub redirects_to: Option<String>,
+}
+
+fn has_table(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+        [name],
+        |row| row.get(0)
+    )
+}
+
+/// Title of the page a redirect leads to and its id, if it was resolved by `wiki4 build`
+fn redirect_target(conn: &Connection, id: i64) -> rusqlite::Result<Option<(i64, String)>> {
+    if !has_table(conn, "redirects")? {
+        return Ok(None);
+    }
+    conn.query_row(
+        "SELECT pages.id, pages.title FROM redirects JOIN pages ON pages.id = redirects.target_id WHERE redirects.source_id = ?",
+        [id],
+        |row| Ok((row.get(0)?, row.get(1)?))
+    ).optional()
+}
+
+/// The page `title` refers to - following it if it is a redirect - or `None` if no page has that title
+pub fn find_page(conn: &Connection, title: &str) -> rusqlite::Result<Option<FoundPage>> {
+    let id = page_id::resolve_title(conn, title)?;
+    let page: Option<(String, bool)> = conn.query_row(
+        "SELECT title, is_redirect FROM pages WHERE id = ?",
+        [id],
+        |row| Ok((row.get(0)?, row.get(1)?))
+    ).optional()?;
+
+    let Some((title, is_redirect)) = page else {
+        return Ok(None);
+    };
+    if is_redirect {
+        if let Some((target_id, target_title)) = redirect_target(conn, id)? {
+            return Ok(Some(FoundPage { id: target_id, title: target_title, redirect: Some(title) }));
+        }
+    }
+
+    Ok(Some(FoundPage { id, title, redirect: None }))
+}
+
+/// Creates the trigram index suggestions are found with, returning whether it had to be created
+pub fn ensure_title_index(conn: &Connection) -> rusqlite::Result<bool> {
+    if has_table(conn, "page_titles")? {
+        return Ok(false);
+    }
+
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE page_titles USING fts5(
+            title,
+            content = 'pages',
+            content_rowid = 'id',
+            tokenize = 'trigram case_sensitive 0'
+        );
+        INSERT INTO page_titles(page_titles) VALUES ('rebuild');"
+    )?;
+    Ok(true)
+}
+
+/// Edit distance between `a` and `b` in characters
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = previous[j] + (a != b) as usize;
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Up to `limit` existing titles close to `title`: titles starting with it first, then the rest by
+/// edit distance (ignoring case). The title index is created if it doesn't exist yet.
+pub fn suggest(conn: &Connection, title: &str, limit: usize) -> rusqlite::Result<Vec<Suggestion>> {
+    ensure_title_index(conn)?;
+
+    let query = title::normalize(title);
+    let folded = query.to_lowercase();
+    if folded.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Id to title, whether it is a redirect and whether it starts with the query
+    let mut candidates: HashMap<i64, (String, bool, bool)> = HashMap::new();
+
+    let escaped = query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    let mut stmt = conn.prepare(
+        "SELECT pages.id, pages.title, pages.is_redirect FROM page_titles JOIN pages ON pages.id = page_titles.rowid
+            WHERE page_titles.title LIKE ? ESCAPE '\\' LIMIT ?"
+    )?;
+    let rows = stmt.query_map((format!("{}%", escaped), CANDIDATES as i64), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    for row in rows {
+        let (id, title, is_redirect) = row?;
+        candidates.insert(id, (title, is_redirect, true));
+    }
+
+    // Titles sharing any three letter sequence with the query, best matches first
+    let chars: Vec<char> = folded.chars().collect();
+    let mut trigrams: Vec<String> = chars.windows(3).map(|w| format!("\"{}\"", w.iter().collect::<String>().replace('"', "\"\""))).collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    if !trigrams.is_empty() {
+        let mut stmt = conn.prepare(
+            "SELECT pages.id, pages.title, pages.is_redirect FROM page_titles JOIN pages ON pages.id = page_titles.rowid
+                WHERE page_titles MATCH ? ORDER BY page_titles.rank LIMIT ?"
+        )?;
+        let rows = stmt.query_map((trigrams.join(" OR "), CANDIDATES as i64), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        for row in rows {
+            let (id, title, is_redirect) = row?;
+            candidates.entry(id).or_insert((title, is_redirect, false));
+        }
+    }
+
+    // "Tagret" has no trigram in common with "Target", so if nothing close was found check every title
+    // that could be a couple of edits away
+    if !candidates.values().any(|(title, _, prefix)| *prefix || levenshtein(&folded, &title.to_lowercase()) <= SCAN_DISTANCE) {
+        let mut stmt = conn.prepare("SELECT id, title, is_redirect FROM pages WHERE length(title) BETWEEN ? AND ?")?;
+        let lengths = (chars.len().saturating_sub(SCAN_DISTANCE) as i64, (chars.len() + SCAN_DISTANCE) as i64);
+        let rows = stmt.query_map(lengths, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        for row in rows {
+            let (id, title, is_redirect): (i64, String, bool) = row?;
+            if levenshtein(&folded, &title.to_lowercase()) <= SCAN_DISTANCE {
+                candidates.insert(id, (title, is_redirect, false));
+            }
+        }
+    }
+
+    let mut ranked: Vec<(bool, usize, i64, String, bool)> = candidates.into_iter()
+        .map(|(id, (title, is_redirect, prefix))| (!prefix, levenshtein(&folded, &title.to_lowercase()), id, title, is_redirect))
+        .collect();
+    ranked.sort_unstable_by(|a, b| (a.0, a.1, a.3.len(), &a.3).cmp(&(b.0, b.1, b.3.len(), &b.3)));
+
+    ranked.into_iter()
+        .take(limit)
+        .map(|(_, _, id, title, is_redirect)| {
+            let redirects_to = if is_redirect { redirect_target(conn, id)?.map(|(_, title)| title) } else { None };
+            Ok(Suggestion { title, redirects_to })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Builder;
+    use crate::wikitext::WikiLink;
+
+    fn titles() -> Connection {
+        let mut builder = Builder::new(":memory:", 10, 100, 100, false).unwrap();
+        for title in ["Target", "Target Page", "London", "Londonderry", "Bedford"] {
+            builder.cache(title.to_string(), Vec::new(), false).unwrap();
+        }
+        let link = WikiLink { text: "London".to_string(), target: "London".to_string(), section: None, offset: 0 };
+        builder.cache("Old London".to_string(), vec![link], true).unwrap();
+        builder.finish(false).unwrap()
+    }
+
+    fn suggested(conn: &Connection, title: &str) -> Vec<(String, Option<String>)> {
+        suggest(conn, title, 10).unwrap().into_iter().map(|s| (s.title, s.redirects_to)).collect()
+    }
+
+    #[test]
+    fn found_pages() {
+        let conn = titles();
+
+        let page = find_page(&conn, "bedford").unwrap().unwrap();
+        assert_eq!((page.id, page.title.as_str(), page.redirect), (page_id::page_id("Bedford"), "Bedford", None));
+
+        // Redirects are followed to their target
+        let page = find_page(&conn, "Old_London").unwrap().unwrap();
+        assert_eq!((page.title.as_str(), page.redirect.as_deref()), ("London", Some("Old London")));
+
+        assert!(find_page(&conn, "Paris").unwrap().is_none());
+    }
+
+    #[test]
+    fn suggestions() {
+        let conn = titles();
+
+        // Titles starting with the query come first, then the rest by edit distance
+        let london = suggested(&conn, "Lond");
+        assert_eq!(london[0].0, "London");
+        assert_eq!(london[1].0, "Londonderry");
+        assert!(london.contains(&("Old London".to_string(), Some("London".to_string()))));
+
+        assert_eq!(suggested(&conn, "Bedfrod")[0].0, "Bedford");
+        assert!(suggested(&conn, "Zzzzzz").is_empty());
+        assert!(suggested(&conn, "").is_empty());
+    }
+
+    #[test]
+    fn short_typos() {
+        let conn = titles();
+
+        // No trigram in common with the title meant, so only the scan finds these
+        assert_eq!(suggested(&conn, "Tagret")[0].0, "Target");
+        assert_eq!(suggested(&conn, "Lodnon")[0].0, "London");
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("bedford", "bedford"), 0);
+        assert_eq!(levenshtein("bedfrod", "bedford"), 2);
+        assert_eq!(levenshtein("london", "londres"), 3);
+        assert_eq!(levenshtein("zürich", "zurich"), 1);
+        assert_eq!(levenshtein("", "abc"), 3);
+    }
+}
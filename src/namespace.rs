@@ -0,0 +1,174 @@
+//! Namespace table used to decide which pages and link targets belong in the graph
+
+use std::collections::HashMap;
+
+pub const MAIN: i64 = 0;
+
+/// Names MediaWiki accepts for a namespace besides the ones a dump lists in `<siteinfo>`
+const ALIASES: [(&str, i64); 7] = [
+    ("WP", 4),
+    ("Project", 4),
+    ("WT", 5),
+    ("Project talk", 5),
+    ("Image", 6),
+    ("Image talk", 7),
+    ("TM", 10),
+];
+
+/// Canonical English namespaces, used when a dump doesn't list its own
+const DEFAULT_NAMESPACES: [(&str, i64); 30] = [
+    ("Media", -2),
+    ("Special", -1),
+    ("Talk", 1),
+    ("User", 2),
+    ("User talk", 3),
+    ("Wikipedia", 4),
+    ("Wikipedia talk", 5),
+    ("File", 6),
+    ("File talk", 7),
+    ("MediaWiki", 8),
+    ("MediaWiki talk", 9),
+    ("Template", 10),
+    ("Template talk", 11),
+    ("Help", 12),
+    ("Help talk", 13),
+    ("Category", 14),
+    ("Category talk", 15),
+    ("Portal", 100),
+    ("Portal talk", 101),
+    ("Draft", 118),
+    ("Draft talk", 119),
+    ("TimedText", 710),
+    ("TimedText talk", 711),
+    ("Module", 828),
+    ("Module talk", 829),
+    ("Gadget", 2300),
+    ("Gadget talk", 2301),
+    ("Gadget definition", 2302),
+    ("Gadget definition talk", 2303),
+    ("Event", 1728),
+];
+
+fn normalise(name: &str) -> String {
+    name.trim().replace('_', " ").to_lowercase()
+}
+
+#[derive(Debug, Clone)]
+pub struct Namespaces {
+    /// Normalised name or alias to key
+    by_name: HashMap<String, i64>,
+    /// Key to the name given by the dump
+    names: HashMap<i64, String>,
+}
+
+impl Namespaces {
+    /// Namespace table with only the aliases - `add` the dump's namespaces to it
+    pub fn new() -> Namespaces {
+        let mut namespaces = Namespaces {
+            by_name: HashMap::new(),
+            names: HashMap::new(),
+        };
+        for (alias, key) in ALIASES {
+            namespaces.by_name.insert(normalise(alias), key);
+        }
+        namespaces
+    }
+
+    pub fn add(&mut self, key: i64, name: &str) {
+        if key == MAIN || name.trim().is_empty() {
+            return;
+        }
+        self.by_name.insert(normalise(name), key);
+        self.names.insert(key, name.to_string());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Key of a namespace from its key or (case insensitive) name or alias
+    pub fn parse(&self, name_or_key: &str) -> Option<i64> {
+        if let Ok(key) = name_or_key.trim().parse() {
+            return Some(key);
+        }
+        if normalise(name_or_key) == "main" || normalise(name_or_key) == "article" {
+            return Some(MAIN);
+        }
+        self.by_name.get(&normalise(name_or_key)).copied()
+    }
+
+    pub fn name(&self, key: i64) -> Option<&str> {
+        self.names.get(&key).map(|n| n.as_str())
+    }
+
+    /// Namespace a link target is in, e.g. `Image:Foo.png` is in 6 (File) and `:Category:Foo` in 14.
+    /// Anything without a known prefix is in the main namespace.
+    pub fn classify(&self, target: &str) -> i64 {
+        let target = target.trim_start();
+        let target = target.strip_prefix(':').unwrap_or(target);
+        match target.split_once(':') {
+            Some((prefix, _)) => self.by_name.get(&normalise(prefix)).copied().unwrap_or(MAIN),
+            None => MAIN,
+        }
+    }
+}
+
+impl Default for Namespaces {
+    fn default() -> Self {
+        let mut namespaces = Namespaces::new();
+        for (name, key) in DEFAULT_NAMESPACES {
+            namespaces.add(key, name);
+        }
+        namespaces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases() {
+        let namespaces = Namespaces::default();
+        assert_eq!(namespaces.classify("Image:Foo.png"), 6);
+        assert_eq!(namespaces.classify("File:Foo.png"), 6);
+        assert_eq!(namespaces.classify("WP:NPOV"), 4);
+        assert_eq!(namespaces.classify("Project talk:Foo"), 5);
+        assert_eq!(namespaces.parse("TM"), Some(10));
+        assert_eq!(namespaces.parse("article"), Some(MAIN));
+        assert_eq!(namespaces.parse("100"), Some(100));
+        assert_eq!(namespaces.name(6), Some("File"));
+    }
+
+    #[test]
+    fn case_and_spacing() {
+        let namespaces = Namespaces::default();
+        assert_eq!(namespaces.classify("category:Foo"), 14);
+        assert_eq!(namespaces.classify("USER_TALK:Foo"), 3);
+        assert_eq!(namespaces.classify("  :Category:Foo"), 14);
+        assert_eq!(namespaces.classify("  Template : Foo"), 10);
+        assert_eq!(namespaces.parse(" help_talk "), Some(13));
+    }
+
+    #[test]
+    fn main_namespace() {
+        let namespaces = Namespaces::default();
+        assert_eq!(namespaces.classify("London"), MAIN);
+        assert_eq!(namespaces.classify("Star Wars: A New Hope"), MAIN);
+        assert_eq!(namespaces.classify(":London"), MAIN);
+        assert_eq!(namespaces.parse("Nonsense"), None);
+    }
+
+    #[test]
+    fn dump_namespaces() {
+        let mut namespaces = Namespaces::new();
+        assert!(namespaces.is_empty());
+        namespaces.add(MAIN, "");
+        namespaces.add(100, "Portal");
+        assert!(!namespaces.is_empty());
+        assert_eq!(namespaces.classify("portal:Trains"), 100);
+        // Only the aliases are known until the dump's namespaces are added
+        assert_eq!(namespaces.classify("Category:Foo"), MAIN);
+        assert_eq!(namespaces.classify("Image:Foo.png"), 6);
+    }
+}
@@ -0,0 +1,197 @@
+//! Page ids are a hash of the page's title so links can be stored before the page they point to has
+//! been seen. The hash has to give the same result on every machine and toolchain (which
+//! `DefaultHasher` doesn't promise), so 64-bit FNV-1a is implemented here and the scheme used is
+//! recorded in each database's `metadata` table.
+//!
+//! Changing how titles are hashed or normalised changes every id - bump `HASH_VERSION` when doing so
+//! and databases built with the old scheme will be refused rather than silently returning nonsense.
+//!
+//! Each build also records a random `build_id`, which graph snapshots copy so a snapshot exported
+//! from an earlier build of the database isn't used with a newer one.
+
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use rusqlite::{Connection, OptionalExtension};
+use crate::title;
+
+pub const HASH_ALGORITHM: &str = "fnv1a-64";
+pub const HASH_VERSION: u32 = 2;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+const FNV_32_OFFSET_BASIS: u32 = 0x811c9dc5;
+const FNV_32_PRIME: u32 = 0x01000193;
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+fn fnv1a_32(bytes: &[u8]) -> u32 {
+    let mut hash = FNV_32_OFFSET_BASIS;
+    for &byte in bytes {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(FNV_32_PRIME);
+    }
+    hash
+}
+
+/// Key titles are compared by - two titles with the same key are the same page
+pub fn title_key(title: &str) -> String {
+    title::key(title)
+}
+
+/// Id of the page with this title - titles that normalise to the same thing apart from case share an id
+pub fn page_id(title: &str) -> i64 {
+    i64::from_ne_bytes(fnv1a(title_key(title).as_bytes()).to_ne_bytes())
+}
+
+/// Id given to a title whose `page_id` collides with a different title's. `salt` starts at 1 and is
+/// increased until the id is free.
+pub fn salted_page_id(title: &str, salt: u32) -> i64 {
+    // 0xFF can't appear in utf-8 so the salted input never equals another title's key
+    let mut bytes = title_key(title).into_bytes();
+    bytes.push(0xFF);
+    bytes.extend_from_slice(&salt.to_le_bytes());
+    i64::from_ne_bytes(fnv1a(&bytes).to_ne_bytes())
+}
+
+/// Independent hash of a title, stored alongside links so links to colliding titles can be told
+/// apart after ingestion
+pub fn title_check(title: &str) -> u32 {
+    fnv1a_32(title_key(title).as_bytes())
+}
+
+/// Id of the page with this title in `conn`, taking hash collisions resolved during ingestion into
+/// account
+pub fn resolve_title(conn: &Connection, title: &str) -> rusqlite::Result<i64> {
+    let id = page_id(title);
+
+    let has_collisions: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'hash_collisions')",
+        [],
+        |row| row.get(0)
+    )?;
+    if !has_collisions {
+        return Ok(id);
+    }
+
+    let mut stmt = conn.prepare("SELECT title, resolved_id FROM hash_collisions WHERE id = ?")?;
+    let mut rows = stmt.query([id])?;
+    while let Some(row) = rows.next()? {
+        let colliding_title: String = row.get(0)?;
+        if title_key(&colliding_title) == title_key(title) {
+            return row.get(1);
+        }
+    }
+
+    Ok(id)
+}
+
+/// Records the scheme used to generate ids in `conn`
+pub fn record_scheme(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS metadata (
+            key TEXT PRIMARY KEY,
+            value TEXT
+        )",
+        ()
+    )?;
+    conn.execute("INSERT OR REPLACE INTO metadata VALUES ('hash_algorithm', ?)", (HASH_ALGORITHM,))?;
+    conn.execute("INSERT OR REPLACE INTO metadata VALUES ('hash_version', ?)", (HASH_VERSION.to_string(),))?;
+    Ok(())
+}
+
+/// Gives `conn` a new `build_id`, which has to be done whenever its pages or links are rebuilt
+pub fn record_build_id(conn: &Connection) -> rusqlite::Result<u64> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
+    let build_id = fnv1a(&[nanos.to_le_bytes(), (process::id() as u64).to_le_bytes()].concat());
+    conn.execute("INSERT OR REPLACE INTO metadata VALUES ('build_id', ?)", (build_id.to_string(),))?;
+    Ok(build_id)
+}
+
+/// `build_id` of `conn`, or 0 for databases built before build ids were recorded
+pub fn build_id(conn: &Connection) -> rusqlite::Result<u64> {
+    let build_id: Option<String> = conn.query_row("SELECT value FROM metadata WHERE key = 'build_id'", [], |row| row.get(0))
+        .optional()?;
+    Ok(build_id.and_then(|id| id.parse().ok()).unwrap_or(0))
+}
+
+/// Checks the ids in `conn` were generated with the same scheme as `page_id`
+pub fn check_scheme(conn: &Connection) -> Result<(), String> {
+    let has_metadata: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'metadata')",
+        [],
+        |row| row.get(0)
+    ).map_err(|e| format!("Failed to read database: {:?}", e))?;
+    if !has_metadata {
+        return Err("Database has no page id scheme recorded - it was built by an older version and needs rebuilding with wiki4 build".to_string());
+    }
+
+    let get = |key: &str| -> Result<Option<String>, String> {
+        conn.query_row("SELECT value FROM metadata WHERE key = ?", (key,), |row| row.get(0))
+            .optional()
+            .map_err(|e| format!("Failed to read database metadata: {:?}", e))
+    };
+
+    let algorithm = get("hash_algorithm")?;
+    let version = get("hash_version")?;
+    if algorithm.as_deref() != Some(HASH_ALGORITHM) || version.as_deref() != Some(HASH_VERSION.to_string().as_str()) {
+        return Err(format!(
+            "Database page ids were built with {} version {} but this build uses {} version {} - rebuild it with wiki4 build",
+            algorithm.as_deref().unwrap_or("<unknown>"),
+            version.as_deref().unwrap_or("<unknown>"),
+            HASH_ALGORITHM,
+            HASH_VERSION
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_vectors() {
+        // From the FNV reference test suite
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+        assert_eq!(fnv1a_32(b""), 0x811c9dc5);
+        assert_eq!(fnv1a_32(b"a"), 0xe40c292c);
+        assert_eq!(fnv1a_32(b"foobar"), 0xbf9cf968);
+    }
+
+    #[test]
+    fn ids() {
+        // Titles are hashed by their key, "foobar"
+        assert_eq!(page_id("Foobar"), 0x85944171f73967e8u64 as i64);
+        assert_eq!(page_id("FOOBAR"), page_id("Foobar"));
+        assert_eq!(title_check("Foobar"), 0xbf9cf968);
+        assert_eq!(salted_page_id("Foobar", 1), 0xdecc743b14c5a754u64 as i64);
+        assert_ne!(salted_page_id("Foobar", 2), salted_page_id("Foobar", 1));
+    }
+
+    #[test]
+    fn scheme() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(check_scheme(&conn).unwrap_err().contains("no page id scheme recorded"));
+
+        record_scheme(&conn).unwrap();
+        assert_eq!(check_scheme(&conn), Ok(()));
+
+        conn.execute("UPDATE metadata SET value = 'xxh64' WHERE key = 'hash_algorithm'", ()).unwrap();
+        assert!(check_scheme(&conn).unwrap_err().contains("built with xxh64 version"));
+
+        record_scheme(&conn).unwrap();
+        let old_version = (HASH_VERSION - 1).to_string();
+        conn.execute("UPDATE metadata SET value = ? WHERE key = 'hash_version'", [&old_version]).unwrap();
+        assert!(check_scheme(&conn).unwrap_err().contains(&format!("{} version {} but", HASH_ALGORITHM, old_version)));
+    }
+}
@@ -0,0 +1,892 @@
+//! Breadth first searches over a `WikiGraph`: the shortest path between two pages, every shortest
+//! path, or the K shortest paths that never visit a page twice
+//!
+//! Searches run from both ends at once, always expanding the side with the smaller frontier a level
+//! at a time, and stop early if they run out of the `Budget` they are given.
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use num_format::{Locale, ToFormattedString};
+use crate::graph::{GraphError, WikiGraph};
+
+/// Page ids from the start of a path to its end
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Path {
+    pub pages: Vec<i64>,
+}
+
+impl Path {
+    /// Number of links clicked (see `links`)
+    pub fn links(&self, graph: &mut dyn WikiGraph) -> Result<usize, GraphError> {
+        links(&self.pages, graph)
+    }
+
+    /// Titles of the pages in order
+    pub fn titles(&self, graph: &mut dyn WikiGraph) -> Result<Vec<String>, GraphError> {
+        self.pages.iter().map(|&id| graph.title(id)).collect()
+    }
+}
+
+/// Number of links clicked going through `pages`. A redirect part way along is a link the reader never
+/// sees - they click through to its target in one go - so only the link that led to it counts.
+pub fn links(pages: &[i64], graph: &mut dyn WikiGraph) -> Result<usize, GraphError> {
+    let mut links = 0;
+    for (i, &page) in pages.iter().enumerate().skip(1) {
+        if i == pages.len() - 1 || !graph.is_redirect(page)? {
+            links += 1;
+        }
+    }
+    Ok(links)
+}
+
+/// Pages and links a search may not use
+#[derive(Clone, Default)]
+pub struct Exclusions {
+    pub pages: HashSet<i64>,
+    /// Source and destination of each link
+    pub links: HashSet<(i64, i64)>,
+}
+
+impl Exclusions {
+    pub fn allows(&self, source_id: i64, destination_id: i64) -> bool {
+        self.links.is_empty() || !self.links.contains(&(source_id, destination_id))
+    }
+}
+
+/// Why a search stopped before finding a path or running out of pages
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    /// Every path short enough has been ruled out
+    Length,
+    Pages,
+    Time,
+    Memory,
+    Interrupted,
+}
+
+/// Where a search had got to when it gave up
+#[derive(Debug)]
+pub struct GaveUp {
+    pub limit: Limit,
+    /// Length in links of the longest paths ruled out
+    pub depth: usize,
+    /// Pages found but not yet expanded
+    pub frontier: usize,
+    pub seen: usize,
+}
+
+pub enum Outcome<T> {
+    Found(T),
+    NoPath,
+    GaveUp(GaveUp),
+}
+
+/// How a search ended and how many pages it saw on the way
+pub struct Search<T> {
+    pub outcome: Outcome<T>,
+    pub seen: usize,
+    /// Pages found but not yet expanded
+    pub frontier: usize,
+}
+
+/// Every shortest path between two pages
+pub struct ShortestPaths {
+    /// Number of shortest paths, saturating at `u128::MAX`
+    pub total: u128,
+    /// The first of them, up to the limit asked for
+    pub paths: Vec<Path>,
+}
+
+/// The shortest paths found without repeating a page
+pub struct KShortestPaths {
+    pub paths: Vec<Path>,
+    /// Set if the budget ran out before K paths were found
+    pub gave_up: Option<GaveUp>,
+}
+
+/// Pages, time and memory searches may use between them, and how much they have used. Nothing is
+/// limited unless a field is set.
+pub struct Budget {
+    pub max_pages: Option<u32>,
+    pub timeout: Option<Duration>,
+    /// Bytes held by the two sides of a search
+    pub max_memory: Option<usize>,
+    /// Stops the search when set, e.g. by a Ctrl-C handler
+    pub interrupted: Arc<AtomicBool>,
+    /// Print how the search is going every 10,000 pages, which is left to tools printing text
+    pub progress: bool,
+    /// Pages expanded so far
+    count: u32,
+    start_time: Instant,
+}
+
+impl Default for Budget {
+    fn default() -> Budget {
+        Budget::new()
+    }
+}
+
+impl Budget {
+    /// A budget with no limits and no progress printed, timed from now
+    pub fn new() -> Budget {
+        Budget {
+            max_pages: None,
+            timeout: None,
+            max_memory: None,
+            interrupted: Arc::new(AtomicBool::new(false)),
+            progress: false,
+            count: 0,
+            start_time: Instant::now(),
+        }
+    }
+
+    pub fn pages_searched(&self) -> u32 {
+        self.count
+    }
+
+    /// The limit reached by a search holding `memory` bytes, if any
+    fn exceeded(&self, memory: usize) -> Option<Limit> {
+        if self.interrupted.load(Ordering::Relaxed) {
+            Some(Limit::Interrupted)
+        }
+        else if self.max_pages.is_some_and(|max_pages| self.count >= max_pages) {
+            Some(Limit::Pages)
+        }
+        else if self.timeout.is_some_and(|timeout| self.start_time.elapsed() >= timeout) {
+            Some(Limit::Time)
+        }
+        else if self.max_memory.is_some_and(|max_memory| memory >= max_memory) {
+            Some(Limit::Memory)
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// One direction of the search. Forward pages point back towards the start, backward pages point on
+/// towards the end.
+struct Side {
+    open_set: VecDeque<i64>,
+    visited: HashSet<LinkedPage>,
+    /// Other pages each page was reached from as close to the root as its first, kept when finding
+    /// every shortest path
+    extra_parents: HashMap<i64, Vec<i64>>,
+    all_paths: bool,
+    forwards: bool,
+    /// Levels expanded in full
+    depth: usize,
+}
+
+impl Side {
+    fn new(root: i64, forwards: bool, all_paths: bool) -> Side {
+        // Not preallocated - a search that ends early or has a memory ceiling shouldn't reserve room
+        // for millions of pages
+        //? Consider linked list
+        let mut open_set = VecDeque::new();
+        open_set.push_back(root);
+        let mut visited = HashSet::new();
+        visited.insert(LinkedPage::new(root, None));
+
+        Side {
+            open_set,
+            visited,
+            extra_parents: HashMap::new(),
+            all_paths,
+            forwards,
+            depth: 0,
+        }
+    }
+
+    /// Rough number of bytes held by the side's sets, leaving out the lists of extra parents
+    fn memory(&self) -> usize {
+        self.visited.capacity() * (size_of::<LinkedPage>() + 1)
+            + self.open_set.capacity() * size_of::<i64>()
+            + self.extra_parents.capacity() * (size_of::<(i64, Vec<i64>)>() + 1)
+    }
+}
+
+/// Why a level wasn't expanded to the end
+enum Stop {
+    Limit(Limit),
+    Graph(GraphError),
+}
+
+impl From<GraphError> for Stop {
+    fn from(e: GraphError) -> Stop {
+        Stop::Graph(e)
+    }
+}
+
+/// Expands every page in the current level of `side` (following links forwards or backwards),
+/// returning the first page that has also been seen from `other`, or the limit reached part way through.
+///
+/// Going on from a redirect to its target is free, so a redirect is expanded as soon as it is reached
+/// rather than queued for the next level: forwards its target is as far from the start as it is, and
+/// backwards it is as far from the end as its target.
+///
+/// When finding every shortest path, a page reached more than once in the same level keeps the other
+/// pages it was reached from there too, and the whole level is expanded so every page where the two
+/// sides meet is returned - together they hold every shortest path.
+fn expand_level(
+    side: &mut Side,
+    other: &Side,
+    exclusions: &Exclusions,
+    graph: &mut dyn WikiGraph,
+    budget: &mut Budget,
+) -> Result<Vec<i64>, Stop> {
+    let mut meeting_points = Vec::new();
+    // Pages first reached in this level, the only ones another parent can be as close to the root
+    let mut level: HashSet<i64> = HashSet::new();
+    // Redirects reached in this level and not yet expanded
+    let mut redirects: Vec<i64> = Vec::new();
+    let mut queued = side.open_set.len();
+
+    while queued > 0 || !redirects.is_empty() {
+        if let Some(limit) = budget.exceeded(side.memory() + other.memory()) {
+            return Err(Stop::Limit(limit));
+        }
+
+        let page = match redirects.pop() {
+            Some(page) => page,
+            None => {
+                queued -= 1;
+                side.open_set.pop_front().unwrap()
+            }
+        };
+
+        budget.count += 1;
+        if budget.progress && budget.count.is_multiple_of(10_000) {
+            println!(
+                "Pages searched: {} [{:?}/page] | Seen: {} | Open set size: {}",
+                budget.count.to_formatted_string(&Locale::en),
+                budget.start_time.elapsed() / budget.count,
+                (side.visited.len() + other.visited.len()).to_formatted_string(&Locale::en),
+                side.open_set.len().to_formatted_string(&Locale::en),
+            );
+        }
+
+        let links = if side.forwards { graph.links(page)? } else { graph.backlinks(page)? };
+
+        for link in links {
+            let (source_id, destination_id) = if side.forwards { (page, link) } else { (link, page) };
+            if exclusions.pages.contains(&link) || !exclusions.allows(source_id, destination_id) {
+                continue;
+            }
+
+            if !side.visited.insert(LinkedPage::new(link, Some(page))) {
+                if side.all_paths && level.contains(&link) {
+                    side.extra_parents.entry(link).or_default().push(page);
+                }
+                continue;
+            }
+
+            if side.all_paths {
+                level.insert(link);
+            }
+
+            if other.visited.contains(&LinkedPage::new(link, None)) {
+                meeting_points.push(link);
+                if !side.all_paths {
+                    return Ok(meeting_points);
+                }
+                continue;
+            }
+
+            if graph.is_redirect(link)? {
+                redirects.push(link);
+            }
+            else {
+                side.open_set.push_back(link);
+            }
+        }
+    }
+
+    side.depth += 1;
+    Ok(meeting_points)
+}
+
+/// Bidirectional breadth first search from `start_id` to `end_id` for a path of at most `max_length`
+/// links that doesn't use `exclusions`, returning how it ended (with the pages where the two sides met)
+/// and both sides, or the error reading the graph that stopped it
+fn bidirectional(
+    graph: &mut dyn WikiGraph,
+    start_id: i64,
+    end_id: i64,
+    all_paths: bool,
+    exclusions: &Exclusions,
+    max_length: Option<usize>,
+    budget: &mut Budget,
+) -> Result<(Outcome<Vec<i64>>, Side, Side), GraphError> {
+    let mut forward = Side::new(start_id, true, all_paths);
+    let mut backward = Side::new(end_id, false, all_paths);
+
+    let outcome = if start_id == end_id { Outcome::Found(vec![start_id]) } else { loop {
+        // Any meeting point the next level finds is this many links from both ends
+        let length = forward.depth + backward.depth + 1;
+        let expanded = if max_length.is_some_and(|max_length| length > max_length) {
+            Err(Stop::Limit(Limit::Length))
+        }
+        // Always expand the smaller frontier - levels are expanded whole so the first meeting
+        // point found is guaranteed to be on a shortest path
+        else if forward.open_set.len() <= backward.open_set.len() {
+            expand_level(&mut forward, &backward, exclusions, graph, budget)
+        }
+        else {
+            expand_level(&mut backward, &forward, exclusions, graph, budget)
+        };
+
+        match expanded {
+            Ok(meeting_points) if !meeting_points.is_empty() => break Outcome::Found(meeting_points),
+            Ok(_) => {
+                if forward.open_set.is_empty() || backward.open_set.is_empty() {
+                    break Outcome::NoPath;
+                }
+            }
+            Err(Stop::Limit(limit)) => break Outcome::GaveUp(GaveUp {
+                limit,
+                depth: forward.depth + backward.depth,
+                frontier: forward.open_set.len() + backward.open_set.len(),
+                seen: forward.visited.len() + backward.visited.len(),
+            }),
+            Err(Stop::Graph(e)) => return Err(e),
+        }
+    }};
+
+    Ok((outcome, forward, backward))
+}
+
+/// `outcome` of the search that left `forward` and `backward`, with the found pages turned into `T`
+fn finish<T>(outcome: Outcome<Vec<i64>>, forward: &Side, backward: &Side, found: impl FnOnce(Vec<i64>) -> T) -> Search<T> {
+    Search {
+        outcome: match outcome {
+            Outcome::Found(meeting_points) => Outcome::Found(found(meeting_points)),
+            Outcome::NoPath => Outcome::NoPath,
+            Outcome::GaveUp(gave_up) => Outcome::GaveUp(gave_up),
+        },
+        seen: forward.visited.len() + backward.visited.len(),
+        frontier: forward.open_set.len() + backward.open_set.len(),
+    }
+}
+
+/// A shortest path from `start_id` to `end_id` of at most `max_length` links that doesn't use `exclusions`
+pub fn shortest_path(
+    graph: &mut dyn WikiGraph,
+    start_id: i64,
+    end_id: i64,
+    exclusions: &Exclusions,
+    max_length: Option<usize>,
+    budget: &mut Budget,
+) -> Result<Search<Path>, GraphError> {
+    let (outcome, forward, backward) = bidirectional(graph, start_id, end_id, false, exclusions, max_length, budget)?;
+    Ok(finish(outcome, &forward, &backward, |meeting_points| Path {
+        pages: LinkedPage::join(meeting_points[0], &forward.visited, &backward.visited),
+    }))
+}
+
+/// Counts every shortest path from `start_id` to `end_id` (of at most `max_length` links, not using
+/// `exclusions`) and lists up to `limit` of them
+pub fn all_shortest_paths(
+    graph: &mut dyn WikiGraph,
+    start_id: i64,
+    end_id: i64,
+    exclusions: &Exclusions,
+    max_length: Option<usize>,
+    limit: usize,
+    budget: &mut Budget,
+) -> Result<Search<ShortestPaths>, GraphError> {
+    let (outcome, forward, backward) = bidirectional(graph, start_id, end_id, true, exclusions, max_length, budget)?;
+    Ok(finish(outcome, &forward, &backward, |meeting_points| {
+        let mut forward_counts = HashMap::new();
+        let mut backward_counts = HashMap::new();
+        let total = meeting_points.iter().fold(0u128, |total, &page| {
+            let before = LinkedPage::count_chains(page, &forward.visited, &forward.extra_parents, &mut forward_counts);
+            let after = LinkedPage::count_chains(page, &backward.visited, &backward.extra_parents, &mut backward_counts);
+            total.saturating_add(before.saturating_mul(after))
+        });
+
+        let paths = LinkedPage::unwind_all(&meeting_points, &forward, &backward, limit)
+            .into_iter()
+            .map(|pages| Path { pages })
+            .collect();
+        ShortestPaths { total, paths }
+    }))
+}
+
+/// Up to `k` shortest paths from `start_id` to `end_id` of at most `max_length` links that visit no page
+/// twice and don't use `exclusions`, shortest first, using Yen's algorithm. If a search for one of the
+/// later paths runs out of budget the paths found so far are returned with where it gave up.
+pub fn k_shortest_paths(
+    graph: &mut dyn WikiGraph,
+    start_id: i64,
+    end_id: i64,
+    k: usize,
+    exclusions: &Exclusions,
+    max_length: Option<usize>,
+    budget: &mut Budget,
+) -> Result<Search<KShortestPaths>, GraphError> {
+    let search = shortest_path(graph, start_id, end_id, exclusions, max_length, budget)?;
+    let (seen, frontier) = (search.seen, search.frontier);
+    let first = match search.outcome {
+        Outcome::Found(path) => path.pages,
+        Outcome::NoPath => return Ok(Search { outcome: Outcome::NoPath, seen, frontier }),
+        Outcome::GaveUp(gave_up) => return Ok(Search { outcome: Outcome::GaveUp(gave_up), seen, frontier }),
+    };
+
+    let mut paths = vec![first];
+    // Ordered by links clicked, then by page ids so ties always come out the same way
+    let mut candidates: BTreeSet<(usize, Vec<i64>)> = BTreeSet::new();
+    let mut gave_up = None;
+
+    'paths: while paths.len() < k {
+        let previous = paths.last().unwrap().clone();
+
+        // Each page of the previous path but the last branches off into a new path
+        for i in 0..previous.len() - 1 {
+            let spur = previous[i];
+            let root = &previous[..=i];
+            // A redirect only leads on to its target, which the previous path already went to
+            if graph.is_redirect(spur)? {
+                continue;
+            }
+
+            let mut spur_exclusions = exclusions.clone();
+            // The links found paths took from here, so the branch is new
+            for path in &paths {
+                if path.len() > i + 1 && &path[..=i] == root {
+                    spur_exclusions.links.insert((spur, path[i + 1]));
+                }
+            }
+            // Going back through the root would visit a page twice
+            spur_exclusions.pages.extend(&root[..i]);
+
+            let root_length = links(root, graph)?;
+            let spur_length = max_length.map(|max_length| max_length - root_length);
+            match bidirectional(graph, spur, end_id, false, &spur_exclusions, spur_length, budget)? {
+                (Outcome::Found(meeting_points), forward, backward) => {
+                    let mut path = root[..i].to_vec();
+                    path.extend(LinkedPage::join(meeting_points[0], &forward.visited, &backward.visited));
+                    if !paths.contains(&path) {
+                        candidates.insert((links(&path, graph)?, path));
+                    }
+                }
+                (Outcome::GaveUp(spur_gave_up), ..) if spur_gave_up.limit != Limit::Length => {
+                    gave_up = Some(spur_gave_up);
+                    break 'paths;
+                }
+                _ => {}
+            }
+        }
+
+        match candidates.pop_first() {
+            Some((_, path)) => paths.push(path),
+            None => break,
+        }
+    }
+
+    Ok(Search {
+        outcome: Outcome::Found(KShortestPaths {
+            paths: paths.into_iter().map(|pages| Path { pages }).collect(),
+            gave_up,
+        }),
+        seen,
+        frontier,
+    })
+}
+
+/// How a search through waypoints ended
+pub struct ViaSearch {
+    pub outcome: Outcome<Path>,
+    /// Stops of the leg with no path (or that was given up on) and the most links it could have had
+    pub leg: Option<(i64, i64, Option<usize>)>,
+}
+
+/// A path from the first of `stops` to the last through each of the others in order, joining the
+/// shortest path between each pair. `max_length` limits the whole path. Each leg is searched on its own,
+/// so the same page can be passed through on two legs.
+pub fn path_via(
+    graph: &mut dyn WikiGraph,
+    stops: &[i64],
+    exclusions: &Exclusions,
+    max_length: Option<usize>,
+    budget: &mut Budget,
+) -> Result<ViaSearch, GraphError> {
+    let mut pages = vec![stops[0]];
+
+    for leg in stops.windows(2) {
+        let so_far = links(&pages, graph)?;
+        let leg_length = max_length.map(|max_length| max_length.saturating_sub(so_far));
+        let outcome = match shortest_path(graph, leg[0], leg[1], exclusions, leg_length, budget)?.outcome {
+            Outcome::Found(path) => {
+                pages.extend(path.pages.into_iter().skip(1));
+                continue;
+            }
+            Outcome::NoPath => Outcome::NoPath,
+            Outcome::GaveUp(gave_up) => Outcome::GaveUp(gave_up),
+        };
+        return Ok(ViaSearch { outcome, leg: Some((leg[0], leg[1], leg_length)) });
+    }
+
+    Ok(ViaSearch { outcome: Outcome::Found(Path { pages }), leg: None })
+}
+
+/// A page reached by a search and the page it was first reached from (towards the side's root).
+/// Hashed and compared by `page` alone so a set of them can be looked up by id.
+struct LinkedPage {
+    page: i64,
+    from: Option<i64>,
+}
+
+impl LinkedPage {
+    fn new(page: i64, from: Option<i64>) -> LinkedPage {
+        LinkedPage {
+            page,
+            from
+        }
+    }
+
+    /// Follows `from` links back to the root of `others`, returning the page ids visited (starting
+    /// with this page)
+    fn chain(&self, others: &HashSet<LinkedPage>) -> Vec<i64> {
+        let mut chain = vec![self.page];
+        let mut from = self.from;
+
+        while let Some(page) = from {
+            chain.push(page);
+            from = others.get(&LinkedPage::new(page, None)).unwrap().from;
+        }
+
+        chain
+    }
+
+    /// Pages `page` was reached from: its first parent and any others as close to the root
+    fn parents(page: i64, visited: &HashSet<LinkedPage>, extra_parents: &HashMap<i64, Vec<i64>>) -> Vec<i64> {
+        let mut parents: Vec<i64> = visited.get(&LinkedPage::new(page, None)).unwrap().from.into_iter().collect();
+        if let Some(extra) = extra_parents.get(&page) {
+            parents.extend(extra);
+        }
+        parents
+    }
+
+    /// Number of shortest chains from `page` back to the root of `visited`
+    fn count_chains(
+        page: i64,
+        visited: &HashSet<LinkedPage>,
+        extra_parents: &HashMap<i64, Vec<i64>>,
+        counts: &mut HashMap<i64, u128>
+    ) -> u128 {
+        if let Some(&count) = counts.get(&page) {
+            return count;
+        }
+
+        let parents = Self::parents(page, visited, extra_parents);
+        let count = if parents.is_empty() {
+            1
+        }
+        else {
+            parents.into_iter().fold(0u128, |count, parent| {
+                count.saturating_add(Self::count_chains(parent, visited, extra_parents, counts))
+            })
+        };
+        counts.insert(page, count);
+        count
+    }
+
+    /// Up to `limit` shortest chains from `page` back to the root of `visited`, each starting with `page`
+    fn all_chains(page: i64, visited: &HashSet<LinkedPage>, extra_parents: &HashMap<i64, Vec<i64>>, limit: usize) -> Vec<Vec<i64>> {
+        let parents = Self::parents(page, visited, extra_parents);
+        if parents.is_empty() {
+            return vec![vec![page]];
+        }
+
+        let mut chains = Vec::new();
+        for parent in parents {
+            if chains.len() >= limit {
+                break;
+            }
+            for chain in Self::all_chains(parent, visited, extra_parents, limit - chains.len()) {
+                let mut full = Vec::with_capacity(chain.len() + 1);
+                full.push(page);
+                full.extend(chain);
+                chains.push(full);
+            }
+        }
+
+        chains
+    }
+
+    /// Up to `limit` shortest paths from the start to the end, joined at each of `meeting_points`
+    fn unwind_all(meeting_points: &[i64], forward: &Side, backward: &Side, limit: usize) -> Vec<Vec<i64>> {
+        let mut paths = Vec::new();
+
+        for &meeting_point in meeting_points {
+            let befores = Self::all_chains(meeting_point, &forward.visited, &forward.extra_parents, limit - paths.len());
+            let afters = Self::all_chains(meeting_point, &backward.visited, &backward.extra_parents, limit - paths.len());
+            for before in &befores {
+                for after in &afters {
+                    if paths.len() >= limit {
+                        return paths;
+                    }
+                    let mut path: Vec<i64> = before.iter().rev().copied().collect();
+                    path.extend(after.iter().skip(1));
+                    paths.push(path);
+                }
+            }
+        }
+
+        paths
+    }
+
+    /// Page ids from the start to `meeting_point` in `forward` followed by those from `meeting_point` to
+    /// the end in `backward`
+    fn join(meeting_point: i64, forward: &HashSet<LinkedPage>, backward: &HashSet<LinkedPage>) -> Vec<i64> {
+        let mut path = forward.get(&LinkedPage::new(meeting_point, None)).unwrap().chain(forward);
+        path.reverse();
+        path.extend(backward.get(&LinkedPage::new(meeting_point, None)).unwrap().chain(backward).into_iter().skip(1));
+        path
+    }
+}
+
+impl Hash for LinkedPage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_i64(self.page);
+    }
+}
+
+impl PartialEq for LinkedPage {
+    fn eq(&self, other: &Self) -> bool {
+        self.page == other.page
+    }
+}
+
+impl Eq for LinkedPage {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::MemoryGraph;
+
+    /// Two shortest paths A-B-D-E and A-C-D-E and a longer one A-F-G-H-E
+    fn diamond() -> (MemoryGraph, HashMap<&'static str, i64>) {
+        let mut graph = MemoryGraph::new();
+        let ids: HashMap<&str, i64> = ["A", "B", "C", "D", "E", "F", "G", "H"].into_iter()
+            .map(|title| (title, graph.add_page(title, false)))
+            .collect();
+        for (source, destination) in [("A", "B"), ("A", "C"), ("B", "D"), ("C", "D"), ("D", "E"), ("A", "F"), ("F", "G"), ("G", "H"), ("H", "E")] {
+            graph.add_link(ids[source], ids[destination]);
+        }
+        (graph, ids)
+    }
+
+    fn found<T>(search: Result<Search<T>, GraphError>) -> T {
+        match search.unwrap().outcome {
+            Outcome::Found(found) => found,
+            _ => panic!("no path found"),
+        }
+    }
+
+    #[test]
+    fn shortest() {
+        let (mut graph, ids) = diamond();
+        let path = found(shortest_path(&mut graph, ids["A"], ids["E"], &Exclusions::default(), None, &mut Budget::new()));
+        assert_eq!(path.links(&mut graph).unwrap(), 3);
+        assert_eq!(path.pages.first(), Some(&ids["A"]));
+        assert_eq!(path.pages.last(), Some(&ids["E"]));
+
+        assert!(matches!(shortest_path(&mut graph, ids["E"], ids["A"], &Exclusions::default(), None, &mut Budget::new()).unwrap().outcome, Outcome::NoPath));
+    }
+
+    #[test]
+    fn excluded_pages() {
+        let (mut graph, ids) = diamond();
+        let mut exclusions = Exclusions::default();
+        exclusions.pages.insert(ids["D"]);
+        let path = found(shortest_path(&mut graph, ids["A"], ids["E"], &exclusions, None, &mut Budget::new()));
+        assert_eq!(path.titles(&mut graph).unwrap(), vec!["A", "F", "G", "H", "E"]);
+    }
+
+    #[test]
+    fn all_shortest() {
+        let (mut graph, ids) = diamond();
+        let paths = found(all_shortest_paths(&mut graph, ids["A"], ids["E"], &Exclusions::default(), None, 10, &mut Budget::new()));
+        assert_eq!(paths.total, 2);
+        assert_eq!(paths.paths.len(), 2);
+        assert_ne!(paths.paths[0], paths.paths[1]);
+        assert!(paths.paths.iter().all(|path| path.links(&mut graph).unwrap() == 3));
+    }
+
+    #[test]
+    fn k_shortest() {
+        let (mut graph, ids) = diamond();
+        let found = found(k_shortest_paths(&mut graph, ids["A"], ids["E"], 5, &Exclusions::default(), None, &mut Budget::new()));
+        assert!(found.gave_up.is_none());
+        assert_eq!(found.paths.iter().map(|path| path.links(&mut graph).unwrap()).collect::<Vec<_>>(), vec![3, 3, 4]);
+    }
+
+    #[test]
+    fn k_shortest_order() {
+        // B and C link to each other, so the shortest paths can also detour through both
+        let (mut graph, ids) = diamond();
+        graph.add_link(ids["B"], ids["C"]);
+        graph.add_link(ids["C"], ids["B"]);
+
+        let every = found(k_shortest_paths(&mut graph, ids["A"], ids["E"], 10, &Exclusions::default(), None, &mut Budget::new()));
+        assert!(every.paths.windows(2).all(|pair| pair[0].links(&mut graph).unwrap() <= pair[1].links(&mut graph).unwrap()));
+        for path in &every.paths {
+            assert_eq!(path.pages.iter().collect::<HashSet<_>>().len(), path.pages.len());
+            for link in path.pages.windows(2) {
+                assert!(graph.links(link[0]).unwrap().contains(&link[1]));
+            }
+        }
+
+        // Every path without a repeated page, each once
+        let mut titles: Vec<String> = every.paths.iter().map(|path| path.titles(&mut graph).unwrap().concat()).collect();
+        titles.sort_unstable();
+        assert_eq!(titles, ["ABCDE", "ABDE", "ACBDE", "ACDE", "AFGHE"]);
+
+        let shortest = found(k_shortest_paths(&mut graph, ids["A"], ids["E"], 10, &Exclusions::default(), Some(3), &mut Budget::new()));
+        assert_eq!(shortest.paths.iter().map(|path| path.links(&mut graph).unwrap()).collect::<Vec<_>>(), vec![3, 3]);
+    }
+
+    #[test]
+    fn avoided_pages() {
+        let (mut graph, ids) = diamond();
+        let mut exclusions = Exclusions::default();
+        exclusions.pages.insert(ids["B"]);
+
+        let paths = found(all_shortest_paths(&mut graph, ids["A"], ids["E"], &exclusions, None, 10, &mut Budget::new()));
+        assert_eq!(paths.total, 1);
+        assert_eq!(paths.paths[0].titles(&mut graph).unwrap(), ["A", "C", "D", "E"]);
+
+        exclusions.pages.insert(ids["D"]);
+        let paths = found(k_shortest_paths(&mut graph, ids["A"], ids["E"], 5, &exclusions, None, &mut Budget::new()));
+        assert_eq!(paths.paths.len(), 1);
+        assert_eq!(paths.paths[0].titles(&mut graph).unwrap(), ["A", "F", "G", "H", "E"]);
+    }
+
+    #[test]
+    fn waypoints() {
+        let (mut graph, ids) = diamond();
+        let stops = |titles: &[&str]| titles.iter().map(|title| ids[title]).collect::<Vec<_>>();
+
+        let via = |graph: &mut MemoryGraph, titles: &[&str], exclusions: &Exclusions, max_length| {
+            path_via(graph, &stops(titles), exclusions, max_length, &mut Budget::new()).unwrap()
+        };
+        let titles = |graph: &mut MemoryGraph, search: ViaSearch| match search.outcome {
+            Outcome::Found(path) => path.titles(graph).unwrap(),
+            _ => panic!("no path found"),
+        };
+
+        // Going through G means taking the long way round
+        let search = via(&mut graph, &["A", "G", "E"], &Exclusions::default(), None);
+        assert_eq!(titles(&mut graph, search), ["A", "F", "G", "H", "E"]);
+
+        let mut exclusions = Exclusions::default();
+        exclusions.pages.insert(ids["B"]);
+        let search = via(&mut graph, &["A", "D", "E"], &exclusions, None);
+        assert_eq!(titles(&mut graph, search), ["A", "C", "D", "E"]);
+
+        // Nothing leads back from E
+        let search = via(&mut graph, &["A", "E", "B"], &Exclusions::default(), None);
+        assert!(matches!(search.outcome, Outcome::NoPath));
+        assert_eq!(search.leg, Some((ids["E"], ids["B"], None)));
+
+        // The length limit is for the whole path, so after two links to G only one is left for the rest
+        let search = via(&mut graph, &["A", "G", "E"], &Exclusions::default(), Some(3));
+        assert!(matches!(search.outcome, Outcome::GaveUp(GaveUp { limit: Limit::Length, .. })));
+        assert_eq!(search.leg, Some((ids["G"], ids["E"], Some(1))));
+    }
+
+    #[test]
+    fn redirects() {
+        // Old London redirects to London, so Bedford is two clicks from England that way as well as
+        // through Luton
+        let mut graph = MemoryGraph::new();
+        let ids: HashMap<&str, i64> = ["Bedford", "Old London", "London", "England", "Luton", "Hertford", "Watford"].into_iter()
+            .map(|title| (title, graph.add_page(title, title == "Old London")))
+            .collect();
+        for (source, destination) in [
+            ("Bedford", "Old London"), ("Old London", "London"), ("London", "England"), ("Bedford", "Luton"),
+            ("Luton", "England"), ("Bedford", "Hertford"), ("Hertford", "Watford"), ("Watford", "England"),
+            ("Watford", "Old London"), ("Hertford", "Luton"),
+        ] {
+            graph.add_link(ids[source], ids[destination]);
+        }
+
+        let paths = found(all_shortest_paths(&mut graph, ids["Bedford"], ids["England"], &Exclusions::default(), Some(2), 10, &mut Budget::new()));
+        assert_eq!(paths.total, 2);
+        let mut titles: Vec<Vec<String>> = paths.paths.iter().map(|path| path.titles(&mut graph).unwrap()).collect();
+        titles.sort_unstable();
+        assert_eq!(titles, [vec!["Bedford", "Luton", "England"], vec!["Bedford", "Old London", "London", "England"]]);
+        assert!(paths.paths.iter().all(|path| path.links(&mut graph).unwrap() == 2));
+
+        let every = found(k_shortest_paths(&mut graph, ids["Bedford"], ids["England"], 10, &Exclusions::default(), None, &mut Budget::new()));
+        assert_eq!(every.paths.iter().map(|path| path.links(&mut graph).unwrap()).collect::<Vec<_>>(), vec![2, 2, 3, 3, 4]);
+
+        // Searching back from London passes through the redirect before the pages that link to it
+        let path = found(shortest_path(&mut graph, ids["Hertford"], ids["London"], &Exclusions::default(), Some(2), &mut Budget::new()));
+        assert_eq!(path.titles(&mut graph).unwrap(), ["Hertford", "Watford", "Old London", "London"]);
+        assert_eq!(path.links(&mut graph).unwrap(), 2);
+        let search = shortest_path(&mut graph, ids["Hertford"], ids["London"], &Exclusions::default(), Some(1), &mut Budget::new()).unwrap();
+        assert!(matches!(search.outcome, Outcome::GaveUp(GaveUp { limit: Limit::Length, .. })));
+    }
+
+    #[test]
+    fn limits() {
+        let (mut graph, ids) = diamond();
+        let search = shortest_path(&mut graph, ids["A"], ids["E"], &Exclusions::default(), Some(2), &mut Budget::new()).unwrap();
+        assert!(matches!(search.outcome, Outcome::GaveUp(GaveUp { limit: Limit::Length, .. })));
+
+        let mut budget = Budget::new();
+        budget.max_pages = Some(1);
+        let search = shortest_path(&mut graph, ids["A"], ids["E"], &Exclusions::default(), None, &mut budget).unwrap();
+        assert!(matches!(search.outcome, Outcome::GaveUp(GaveUp { limit: Limit::Pages, .. })));
+    }
+
+    #[test]
+    fn stopping() {
+        let (mut graph, ids) = diamond();
+        let search = |graph: &mut MemoryGraph, budget: &mut Budget| {
+            shortest_path(graph, ids["A"], ids["E"], &Exclusions::default(), None, budget).unwrap().outcome
+        };
+        let limit = |outcome: Outcome<Path>| match outcome {
+            Outcome::GaveUp(gave_up) => gave_up.limit,
+            _ => panic!("search didn't give up"),
+        };
+
+        // Stops as soon as the most pages have been searched, and not before
+        let mut budget = Budget::new();
+        budget.max_pages = Some(2);
+        assert_eq!(limit(search(&mut graph, &mut budget)), Limit::Pages);
+        assert_eq!(budget.pages_searched(), 2);
+
+        let mut unlimited = Budget::new();
+        assert!(matches!(search(&mut graph, &mut unlimited), Outcome::Found(_)));
+        let mut budget = Budget::new();
+        budget.max_pages = Some(unlimited.pages_searched());
+        assert!(matches!(search(&mut graph, &mut budget), Outcome::Found(_)));
+
+        let mut budget = Budget::new();
+        budget.timeout = Some(Duration::ZERO);
+        assert_eq!(limit(search(&mut graph, &mut budget)), Limit::Time);
+
+        let mut budget = Budget::new();
+        budget.max_memory = Some(0);
+        assert_eq!(limit(search(&mut graph, &mut budget)), Limit::Memory);
+
+        let mut budget = Budget::new();
+        budget.interrupted.store(true, Ordering::Relaxed);
+        assert_eq!(limit(search(&mut graph, &mut budget)), Limit::Interrupted);
+        assert_eq!(budget.pages_searched(), 0);
+
+        // Running out while looking for later paths keeps the ones already found
+        let mut budget = Budget::new();
+        budget.max_pages = Some(unlimited.pages_searched() + 1);
+        let paths = found(k_shortest_paths(&mut graph, ids["A"], ids["E"], 5, &Exclusions::default(), None, &mut budget));
+        assert!(!paths.paths.is_empty() && paths.paths.len() < 3);
+        assert!(matches!(paths.gave_up, Some(GaveUp { limit: Limit::Pages, .. })));
+    }
+}
@@ -0,0 +1,125 @@
+//! MediaWiki's title rules, so `Foo_bar`, `foo bar`, `Foo%20bar` and `Foo&#32;bar` in a link all
+//! reach the page titled `Foo bar`
+
+/// Named character references MediaWiki titles and link targets commonly contain
+const ENTITIES: [(&str, char); 10] = [
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", ' '),
+    ("ndash", '–'),
+    ("mdash", '—'),
+    ("hellip", '…'),
+    ("shy", '\u{ad}'),
+];
+
+/// Replaces `&name;`, `&#123;` and `&#x7B;` references, leaving anything unrecognised as written
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded += &rest[..start];
+        rest = &rest[start..];
+
+        let reference = rest[1..].find(';').and_then(|end| {
+            let name = &rest[1..end + 1];
+            let c = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            }
+            else if let Some(decimal) = name.strip_prefix('#') {
+                decimal.parse().ok().and_then(char::from_u32)
+            }
+            else {
+                ENTITIES.iter().find(|(entity, _)| *entity == name).map(|&(_, c)| c)
+            };
+            c.map(|c| (c, end + 2))
+        });
+
+        match reference {
+            Some((c, length)) => {
+                decoded.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded + rest
+}
+
+/// Title as MediaWiki would store it: entities and percent-encoding decoded, underscores and runs of
+/// whitespace turned into single spaces, trimmed and with its first letter capitalised
+pub fn normalize(title: &str) -> String {
+    let mut title = decode_entities(title);
+    if title.contains('%') {
+        title = url_escape::decode(&title).into_owned();
+    }
+
+    let mut normalized = String::with_capacity(title.len());
+    for word in title.split(|c: char| c == '_' || c.is_whitespace()).filter(|w| !w.is_empty()) {
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        normalized += word;
+    }
+
+    let mut chars = normalized.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => normalized,
+    }
+}
+
+/// Key titles are compared by: the normalised title, case folded so every letter (not only ASCII ones)
+/// matches regardless of case. Upper then lower casing folds the cases `to_lowercase` alone misses,
+/// such as `ß`/`SS` and final sigma.
+pub fn key(title: &str) -> String {
+    normalize(title).to_uppercase().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spaces_and_underscores() {
+        assert_eq!(normalize("  foo_bar   baz_ "), "Foo bar baz");
+        assert_eq!(normalize("__"), "");
+    }
+
+    #[test]
+    fn first_letter() {
+        assert_eq!(normalize("éMILE zola"), "ÉMILE zola");
+        assert_eq!(normalize("ωmega"), "Ωmega");
+        assert_eq!(normalize("iPod"), "IPod");
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(normalize("AT&amp;T"), "AT&T");
+        assert_eq!(normalize("Foo&#32;bar&#x21;"), "Foo bar!");
+        assert_eq!(normalize("Rock &nbsp;roll"), "Rock roll");
+        assert_eq!(normalize("Q&A &unknown; &#xZZ;"), "Q&A &unknown; &#xZZ;");
+    }
+
+    #[test]
+    fn percent_encoding() {
+        assert_eq!(normalize("Caf%C3%A9_society"), "Café society");
+        assert_eq!(normalize("100%"), "100%");
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(key("Émile"), key("émile"));
+        assert_eq!(key("ΩMEGA"), key("ωmega"));
+        assert_eq!(key("Straße"), key("STRASSE"));
+        assert_eq!(key("Foo_Bar"), key("foo%20bar"));
+        assert_ne!(key("Foo"), key("Food"));
+    }
+}
@@ -0,0 +1,415 @@
+//! Finds the article links in a page's wikitext, skipping the places `[[` doesn't make a visible link
+//! (comments, `<nowiki>`, `<pre>`, ...) and, depending on `LinkOptions`, links in templates, citations,
+//! file captions and sections like "See also"
+
+use crate::namespace::Namespaces;
+
+pub const FILE_NAMESPACE: i64 = 6;
+
+/// Tags whose contents aren't parsed as wikitext
+const UNPARSED_TAGS: [&str; 8] = ["nowiki", "pre", "math", "syntaxhighlight", "source", "score", "chem", "ce"];
+
+#[derive(Debug, Clone)]
+pub struct LinkOptions {
+    /// Sections (matched case insensitively, along with their subsections) whose links are ignored
+    pub excluded_sections: Vec<String>,
+    /// Count links inside `{{template}}` arguments, e.g. infobox fields
+    pub template_links: bool,
+    /// Count links inside `<ref>` citations
+    pub ref_links: bool,
+    /// Count links inside `[[File:...|caption]]` captions
+    pub caption_links: bool,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        LinkOptions {
+            excluded_sections: [
+                "See also",
+                "Notes",
+                "Footnotes",
+                "Citations",
+                "References",
+                "Sources",
+                "Bibliography",
+                "Further reading",
+                "External links",
+            ].map(|s| s.to_string()).to_vec(),
+            template_links: false,
+            ref_links: false,
+            caption_links: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WikiLink {
+    /// Linked page without any `#section`, as written (e.g. may start with `:` or a namespace)
+    pub target: String,
+    /// Text the reader sees and clicks on
+    pub text: String,
+    /// Heading of the section the link is in, or `None` in the lead before the first heading
+    pub section: Option<String>,
+    /// Byte offset of the link's `[[` in the page's text
+    pub offset: usize,
+}
+
+/// Index just after the first case insensitive match of `pattern` in `text` at or after `from`
+fn find_ignore_case(text: &str, pattern: &str, from: usize) -> Option<usize> {
+    let pattern = pattern.as_bytes();
+    text.as_bytes()[from..]
+        .windows(pattern.len())
+        .position(|w| w.eq_ignore_ascii_case(pattern))
+        .map(|p| from + p + pattern.len())
+}
+
+/// If `text[start..]` opens a tag, its lowercase name, where the opening tag ends and whether it
+/// closes itself
+fn parse_tag(text: &str, start: usize) -> Option<(String, usize, bool)> {
+    let bytes = text.as_bytes();
+    let name_end = start + 1 + bytes[start + 1..].iter().take_while(|b| b.is_ascii_alphabetic()).count();
+    if name_end == start + 1 {
+        return None;
+    }
+    if !matches!(bytes.get(name_end), Some(b'>' | b'/' | b' ' | b'\t' | b'\n')) {
+        return None;
+    }
+
+    let tag_end = start + bytes[start..].iter().position(|&b| b == b'>')? + 1;
+    let self_closing = bytes[tag_end - 2] == b'/';
+    Some((text[start + 1..name_end].to_ascii_lowercase(), tag_end, self_closing))
+}
+
+/// Index after the element starting with the opening tag `name` that ends at `tag_end`
+fn skip_element(text: &str, name: &str, tag_end: usize) -> usize {
+    match find_ignore_case(text, &format!("</{}", name), tag_end) {
+        Some(close) => text[close..].find('>').map(|p| close + p + 1).unwrap_or(text.len()),
+        None => text.len(),
+    }
+}
+
+/// If `text[start..]` is a heading line, its level, title and where the line ends
+fn parse_heading(text: &str, start: usize) -> Option<(usize, String, usize)> {
+    let line_end = text[start..].find('\n').map(|p| start + p).unwrap_or(text.len());
+    let line = text[start..line_end].trim_end();
+
+    let opening = line.bytes().take_while(|&b| b == b'=').count();
+    let closing = line.bytes().rev().take_while(|&b| b == b'=').count();
+    let level = opening.min(closing);
+    if level == 0 || line.len() <= level * 2 {
+        return None;
+    }
+
+    Some((level, line[level..line.len() - level].trim().to_string(), line_end))
+}
+
+/// If `text[start..]` is a well-formed `[[link]]`, its target, the text after the `|` (if any) and
+/// where it ends
+fn parse_link(text: &str, start: usize) -> Option<(&str, Option<&str>, usize)> {
+    let bytes = text.as_bytes();
+    let target_start = start + 2;
+
+    let mut i = target_start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b']' if bytes.get(i + 1) == Some(&b']') => {
+                return Some((&text[target_start..i], None, i + 2));
+            }
+            b'|' => break,
+            b'[' | b']' | b'{' | b'}' | b'<' | b'>' | b'\n' => return None,
+            _ => i += 1,
+        }
+    }
+    if i >= bytes.len() {
+        return None;
+    }
+
+    // Captions can contain links of their own
+    let text_start = i + 1;
+    let mut depth = 0;
+    let mut i = text_start;
+    while i + 1 < bytes.len() {
+        if bytes[i] == b'[' && bytes[i + 1] == b'[' {
+            depth += 1;
+            i += 2;
+        }
+        else if bytes[i] == b']' && bytes[i + 1] == b']' {
+            if depth == 0 {
+                return Some((&text[target_start..text_start - 1], Some(&text[text_start..i]), i + 2));
+            }
+            depth -= 1;
+            i += 2;
+        }
+        else if bytes[i] == b'\n' && bytes[i + 1] == b'\n' {
+            return None;
+        }
+        else {
+            i += 1;
+        }
+    }
+
+    None
+}
+
+/// Text a link displays: the text after the `|` (or the target, for `[[Target]]` and the `[[Target|]]`
+/// pipe trick) followed by any letters directly after the `]]`, with bold and italic quotes removed
+fn display_text(target: &str, link_text: Option<&str>, trail: &str, namespaces: &Namespaces) -> String {
+    let target = target.trim();
+    let text = match link_text.map(|t| t.trim()) {
+        Some("") => {
+            // The pipe trick drops the namespace and any trailing "(disambiguation)"
+            let without_colon = target.strip_prefix(':').unwrap_or(target);
+            let name = match without_colon.split_once(':') {
+                Some((_, name)) if namespaces.classify(without_colon) != crate::namespace::MAIN => name,
+                _ => without_colon,
+            };
+            match name.rfind(" (") {
+                Some(p) if name.ends_with(')') => name[..p].to_string(),
+                _ => name.to_string(),
+            }
+        }
+        Some(text) => text.to_string(),
+        None => target.strip_prefix(':').unwrap_or(target).to_string(),
+    };
+
+    text.replace("'''", "").replace("''", "") + trail
+}
+
+/// Links in `text` in the order they appear
+pub fn extract_links(text: &str, namespaces: &Namespaces, options: &LinkOptions) -> Vec<WikiLink> {
+    let mut links = Vec::new();
+    extract_links_into(text, 0, None, namespaces, options, &mut links);
+    links
+}
+
+/// Adds the links in `text` (found `base` bytes into the page, in `section`) to `links`
+fn extract_links_into(
+    text: &str,
+    base: usize,
+    mut section: Option<String>,
+    namespaces: &Namespaces,
+    options: &LinkOptions,
+    links: &mut Vec<WikiLink>,
+) {
+    let bytes = text.as_bytes();
+    let mut template_depth = 0;
+    // Level of the excluded section being skipped
+    let mut excluded_level: Option<usize> = None;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let at_line_start = i == 0 || bytes[i - 1] == b'\n';
+
+        if at_line_start && bytes[i] == b'=' && template_depth == 0 {
+            if let Some((level, title, line_end)) = parse_heading(text, i) {
+                if excluded_level.is_some_and(|excluded| level <= excluded) {
+                    excluded_level = None;
+                }
+                if excluded_level.is_none() && options.excluded_sections.iter().any(|s| s.eq_ignore_ascii_case(&title)) {
+                    excluded_level = Some(level);
+                }
+                section = Some(title.replace("'''", "").replace("''", ""));
+                i = line_end;
+                continue;
+            }
+        }
+
+        if bytes[i..].starts_with(b"<!--") {
+            i = text[i + 4..].find("-->").map(|p| i + 4 + p + 3).unwrap_or(text.len());
+            continue;
+        }
+
+        if bytes[i] == b'<' {
+            if let Some((name, tag_end, self_closing)) = parse_tag(text, i) {
+                let unparsed = UNPARSED_TAGS.contains(&name.as_str())
+                    || (name == "ref" && !options.ref_links)
+                    || name == "gallery";
+                if unparsed {
+                    i = if self_closing { tag_end } else { skip_element(text, &name, tag_end) };
+                    continue;
+                }
+            }
+            i += 1;
+            continue;
+        }
+
+        if bytes[i..].starts_with(b"{{") {
+            template_depth += 1;
+            i += 2;
+            continue;
+        }
+        if template_depth > 0 && bytes[i..].starts_with(b"}}") {
+            template_depth -= 1;
+            i += 2;
+            continue;
+        }
+
+        if bytes[i..].starts_with(b"[[") {
+            let Some((target, link_text, end)) = parse_link(text, i) else {
+                i += 2;
+                continue;
+            };
+
+            let counted = excluded_level.is_none() && (template_depth == 0 || options.template_links);
+            if counted {
+                // Letters straight after a link become part of it, e.g. `[[Bus]]es`
+                let trail_length = bytes[end..].iter().take_while(|b| b.is_ascii_lowercase()).count();
+                let trail = &text[end..end + trail_length];
+
+                let page = target.split('#').next().unwrap().trim();
+                if !page.is_empty() {
+                    links.push(WikiLink {
+                        target: page.to_string(),
+                        text: display_text(target, link_text, trail, namespaces),
+                        section: section.clone(),
+                        offset: base + i,
+                    });
+                }
+
+                if let Some(link_text) = link_text {
+                    if options.caption_links && namespaces.classify(target) == FILE_NAMESPACE {
+                        // Captions are slices of `text`, so their offset is where they start in it
+                        let caption_start = link_text.as_ptr() as usize - text.as_ptr() as usize;
+                        extract_links_into(link_text, base + caption_start, section.clone(), namespaces, options, links);
+                    }
+                }
+            }
+
+            i = end;
+            continue;
+        }
+
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(text: &str) -> Vec<String> {
+        links_with(text, &LinkOptions::default())
+    }
+
+    fn links_with(text: &str, options: &LinkOptions) -> Vec<String> {
+        extract_links(text, &Namespaces::default(), options).into_iter().map(|l| l.target).collect()
+    }
+
+    #[test]
+    fn plain_links() {
+        assert_eq!(links("A [[Foo]] and [[Bar|the bar]] and [[Baz#History|baz]]."), ["Foo", "Bar", "Baz"]);
+    }
+
+    #[test]
+    fn link_text() {
+        let text = "[[Bus]]es, [[iPhone|''the'' phone]], [[Paris (band)|]], [[:Category:Cats|]], [[Mercury#Orbit]]";
+        let texts: Vec<String> = extract_links(text, &Namespaces::default(), &LinkOptions::default())
+            .into_iter()
+            .map(|l| l.text)
+            .collect();
+        assert_eq!(texts, ["Buses", "the phone", "Paris", "Cats", "Mercury#Orbit"]);
+    }
+
+    #[test]
+    fn positions() {
+        let text = "[[A]]\n== ''History'' ==\n[[File:X.png|[[B]]]]\n=== Later ===\n[[C]]";
+        let links: Vec<(Option<String>, usize)> = extract_links(text, &Namespaces::default(), &LinkOptions::default())
+            .into_iter()
+            .map(|l| (l.section, l.offset))
+            .collect();
+        assert_eq!(links, [
+            (None, 0),
+            (Some("History".to_string()), 24),
+            (Some("History".to_string()), 37),
+            (Some("Later".to_string()), 59),
+        ]);
+        assert_eq!(&text[37..42], "[[B]]");
+    }
+
+    #[test]
+    fn section_links_are_skipped() {
+        assert!(links("See [[#Early life|below]].").is_empty());
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(links("[[A]]<!-- [[B]] \n [[C]] -->[[D]]"), ["A", "D"]);
+        assert_eq!(links("[[A]]<!-- unclosed [[B]]"), ["A"]);
+    }
+
+    #[test]
+    fn unparsed_tags() {
+        assert_eq!(links("<nowiki>[[A]]</nowiki>[[B]]<pre>[[C]]</pre><math>[[D]]</math>"), ["B"]);
+        assert_eq!(links("<NoWiki>[[A]]</NOWIKI>[[B]]<nowiki/>[[C]]"), ["B", "C"]);
+    }
+
+    #[test]
+    fn refs() {
+        let text = "[[A]]<ref name=\"x\">[[B]]</ref>[[C]]<ref name=\"x\" />[[D]]";
+        assert_eq!(links(text), ["A", "C", "D"]);
+
+        let options = LinkOptions { ref_links: true, ..LinkOptions::default() };
+        assert_eq!(links_with(text, &options), ["A", "B", "C", "D"]);
+    }
+
+    #[test]
+    fn templates() {
+        let text = "{{Infobox|birth_place=[[London]]|spouse={{marriage|[[Jane]]}}}}[[A]] {{cite|[[B]]}} [[C]]";
+        assert_eq!(links(text), ["A", "C"]);
+
+        let options = LinkOptions { template_links: true, ..LinkOptions::default() };
+        assert_eq!(links_with(text, &options), ["London", "Jane", "A", "B", "C"]);
+    }
+
+    #[test]
+    fn file_captions() {
+        let text = "[[File:Map.png|thumb|A map of [[France]] and [[Spain|its neighbour]]]] [[Paris]]";
+        assert_eq!(links(text), ["File:Map.png", "France", "Spain", "Paris"]);
+        assert_eq!(links("[[Image:X.jpg|[[Y]]]]"), ["Image:X.jpg", "Y"]);
+
+        let options = LinkOptions { caption_links: false, ..LinkOptions::default() };
+        assert_eq!(links_with(text, &options), ["File:Map.png", "Paris"]);
+    }
+
+    #[test]
+    fn galleries() {
+        assert_eq!(links("<gallery>\nFile:A.png|[[B]]\n</gallery>[[C]]"), ["C"]);
+    }
+
+    #[test]
+    fn excluded_sections() {
+        let text = "[[A]]\n== See also ==\n[[B]]\n=== More ===\n[[C]]\n==Legacy==\n[[D]]\n==References==\n[[E]]";
+        assert_eq!(links(text), ["A", "D"]);
+
+        let options = LinkOptions { excluded_sections: vec![], ..LinkOptions::default() };
+        assert_eq!(links_with(text, &options), ["A", "B", "C", "D", "E"]);
+    }
+
+    #[test]
+    fn headings_need_to_start_a_line() {
+        assert_eq!(links("x == See also == [[A]]\n==see ALSO==  \n[[B]]"), ["A"]);
+    }
+
+    #[test]
+    fn malformed_links() {
+        assert_eq!(links("[[A\nB]] [[C]]"), ["C"]);
+        assert_eq!(links("[[Unclosed [[D]]"), ["D"]);
+        assert_eq!(links("[[E|text\n\nmore]] [[F]]"), ["F"]);
+        assert_eq!(links("[[G"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn external_links() {
+        assert_eq!(links("[https://example.com Example] [[A]] [[https://example.com]]"), ["A", "https://example.com"]);
+    }
+
+    #[test]
+    fn leading_colons_are_kept() {
+        assert_eq!(links("[[:Category:Cats]] [[ Dogs ]]"), [":Category:Cats", "Dogs"]);
+    }
+
+    #[test]
+    fn non_ascii() {
+        assert_eq!(links("Ünïcödé [[Zürich]] — <!-- é --> [[東京|Tōkyō]]\n== Véase ==\n[[Ñ]]"), ["Zürich", "東京", "Ñ"]);
+    }
+}